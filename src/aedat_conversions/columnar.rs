@@ -0,0 +1,229 @@
+use std::{fs::File, io, sync::Arc};
+
+use arrow_array::{
+    ArrayRef, Float32Array, Int64Array, Int8Array, RecordBatch, UInt16Array, UInt32Array,
+};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    file::properties::WriterProperties,
+};
+
+use crate::{
+    aedat_data::{CameraParameters, Event},
+    cli_configs::{CoordMode, CsvConfig, EventFormat},
+    undistort::Undistortion,
+};
+
+/// Number of events in each record batch, which is also the Parquet row group size
+const BATCH_SIZE: usize = 1_000_000;
+
+/// Schema with the same columns as the CSV export, in the same order
+#[must_use]
+pub fn event_schema(config: &CsvConfig) -> Schema {
+    let mut fields = Vec::new();
+
+    if config.include_polarity {
+        fields.push(Field::new("polarity", DataType::Int8, false));
+    }
+
+    match config.coords {
+        CoordMode::NoCoord => (),
+        CoordMode::XY => {
+            fields.push(Field::new("x", DataType::UInt16, false));
+            fields.push(Field::new("y", DataType::UInt16, false));
+        }
+        CoordMode::PixelNum => fields.push(Field::new("pixel_number", DataType::UInt32, false)),
+    }
+
+    if config.calibration.is_some() {
+        // Rounded coordinates are null when they are mapped outside of the sensor
+        if config.round_undistorted {
+            fields.push(Field::new("x_undist", DataType::UInt16, true));
+            fields.push(Field::new("y_undist", DataType::UInt16, true));
+        } else {
+            fields.push(Field::new("x_undist", DataType::Float32, false));
+            fields.push(Field::new("y_undist", DataType::Float32, false));
+        }
+    }
+
+    fields.push(Field::new("timestamp", DataType::Int64, false));
+
+    Schema::new(fields)
+}
+
+fn event_batch(
+    events: &[Event],
+    schema: &SchemaRef,
+    config: &CsvConfig,
+    cam: &CameraParameters,
+    undistortion: Option<&Undistortion>,
+    time_offset: i32,
+) -> io::Result<RecordBatch> {
+    let dimensions = config.transform.dimensions(cam);
+    let mut columns: Vec<ArrayRef> = Vec::new();
+
+    let coords: Vec<(u8, u8)> = events
+        .iter()
+        .map(|event| config.transform.event_coords(event, cam))
+        .collect();
+
+    if config.include_polarity {
+        let polarity: Int8Array = events
+            .iter()
+            .map(|event| {
+                if event.get_polarity(&cam.camera_type) {
+                    1
+                } else {
+                    -1
+                }
+            })
+            .collect::<Vec<i8>>()
+            .into();
+        columns.push(Arc::new(polarity));
+    }
+
+    match config.coords {
+        CoordMode::NoCoord => (),
+        CoordMode::XY => {
+            let x: UInt16Array = coords
+                .iter()
+                .map(|&(x, _)| u16::from(x))
+                .collect::<Vec<_>>()
+                .into();
+            let y: UInt16Array = coords
+                .iter()
+                .map(|&(_, y)| u16::from(y))
+                .collect::<Vec<_>>()
+                .into();
+            columns.push(Arc::new(x));
+            columns.push(Arc::new(y));
+        }
+        CoordMode::PixelNum => {
+            let pixel_number: UInt32Array = coords
+                .iter()
+                .map(|&coords| config.pixel_numbering.pixel_number(coords, dimensions))
+                .collect::<Vec<_>>()
+                .into();
+            columns.push(Arc::new(pixel_number));
+        }
+    }
+
+    if let Some(undistortion) = undistortion {
+        let sensor_coords = events
+            .iter()
+            .map(|event| event.get_coords(&cam.camera_type));
+
+        if config.round_undistorted {
+            let undistorted: Vec<Option<(u8, u8)>> = sensor_coords
+                .map(|coords| {
+                    undistortion
+                        .undistort_rounded(coords)
+                        .map(|coords| config.transform.apply(coords, cam))
+                })
+                .collect();
+
+            let x: UInt16Array = undistorted
+                .iter()
+                .map(|coords| coords.map(|(x, _)| u16::from(x)))
+                .collect();
+            let y: UInt16Array = undistorted
+                .iter()
+                .map(|coords| coords.map(|(_, y)| u16::from(y)))
+                .collect();
+            columns.push(Arc::new(x));
+            columns.push(Arc::new(y));
+        } else {
+            let undistorted: Vec<(f32, f32)> = sensor_coords
+                .map(|coords| {
+                    config
+                        .transform
+                        .apply_f32(undistortion.undistort(coords), cam)
+                })
+                .collect();
+
+            let x: Float32Array = undistorted
+                .iter()
+                .map(|&(x, _)| x)
+                .collect::<Vec<_>>()
+                .into();
+            let y: Float32Array = undistorted
+                .iter()
+                .map(|&(_, y)| y)
+                .collect::<Vec<_>>()
+                .into();
+            columns.push(Arc::new(x));
+            columns.push(Arc::new(y));
+        }
+    }
+
+    let timestamps: Int64Array = events
+        .iter()
+        .map(|event| i64::from(event.get_timestamp() - time_offset))
+        .collect::<Vec<_>>()
+        .into();
+    columns.push(Arc::new(timestamps));
+
+    RecordBatch::try_new(schema.clone(), columns).map_err(io::Error::other)
+}
+
+/// Export events as typed columns to a Parquet file or an Arrow IPC file
+pub fn create_columnar(
+    events: Vec<Event>,
+    config: &CsvConfig,
+    cam: &CameraParameters,
+) -> io::Result<()> {
+    let schema: SchemaRef = Arc::new(event_schema(config));
+
+    let time_offset = match events.first() {
+        Some(event) if config.offset_time => event.get_timestamp(),
+        _ => 0,
+    };
+
+    let undistortion = match &config.calibration {
+        Some(path) => Some(Undistortion::from_calibration_file(path, cam)?),
+        None => None,
+    };
+
+    let file = File::create(&config.filename)?;
+    let batches = events.chunks(BATCH_SIZE).map(|chunk| {
+        event_batch(
+            chunk,
+            &schema,
+            config,
+            cam,
+            undistortion.as_ref(),
+            time_offset,
+        )
+    });
+
+    match config.format {
+        EventFormat::Parquet => {
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::ZSTD(ZstdLevel::default()))
+                .set_max_row_group_size(BATCH_SIZE)
+                .build();
+
+            let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))
+                .map_err(io::Error::other)?;
+            for batch in batches {
+                writer.write(&batch?).map_err(io::Error::other)?;
+            }
+            writer.close().map_err(io::Error::other)?;
+        }
+        EventFormat::ArrowIpc => {
+            let mut writer = FileWriter::try_new(file, &schema).map_err(io::Error::other)?;
+            for batch in batches {
+                writer.write(&batch?).map_err(io::Error::other)?;
+            }
+            writer.finish().map_err(io::Error::other)?;
+        }
+        EventFormat::Csv | EventFormat::Npy | EventFormat::Npz => {
+            unreachable!("create_columnar is only called for Parquet and Arrow IPC exports")
+        }
+    }
+
+    Ok(())
+}
@@ -1,41 +1,113 @@
-use std::{fs::File, io::Write};
+use std::io::{self, Write};
 
 use crate::{
     aedat_data::{CameraParameters, Event},
-    cli_configs::{CoordMode, CsvConfig},
+    cli_configs::{CsvConfig, EventColumn, PolarityEncoding, TimeUnit},
+    compression::OutputWriter,
+    undistort::Undistortion,
 };
 
-fn format_polarity(polarity: bool) -> String {
-    format!("{},", if polarity { "1" } else { "-1" })
+fn format_polarity(polarity: bool, encoding: &PolarityEncoding) -> &'static str {
+    match (encoding, polarity) {
+        (_, true) => "1",
+        (PolarityEncoding::Signed, false) => "-1",
+        (PolarityEncoding::Binary, false) => "0",
+    }
 }
 
-fn config_csv_header(config: &CsvConfig) -> String {
-    let mut header_tmp = String::new();
-
-    if config.include_polarity {
-        header_tmp.push_str("On/Off,");
+fn format_timestamp(timestamp: i32, unit: &TimeUnit) -> String {
+    match unit {
+        TimeUnit::Microseconds => timestamp.to_string(),
+        TimeUnit::Milliseconds => format!("{:.3}", f64::from(timestamp) / 1_000.0),
+        TimeUnit::Seconds => format!("{:.6}", f64::from(timestamp) / 1_000_000.0),
     }
+}
 
-    match config.coords {
-        CoordMode::NoCoord => (),
-        CoordMode::XY => header_tmp.push_str("X,Y,"),
-        CoordMode::PixelNum => header_tmp.push_str("Pixel Number,"),
-    };
+fn config_csv_header(columns: &[EventColumn], delimiter: char) -> String {
+    let mut header_tmp = columns
+        .iter()
+        .map(EventColumn::header)
+        .collect::<Vec<_>>()
+        .join(&delimiter.to_string());
 
-    header_tmp.push_str("Timestamp\n");
+    header_tmp.push('\n');
 
     header_tmp
 }
 
-fn format_coords_xy(x: u8, y: u8) -> String {
-    format!("{x},{y},")
+/// Undistorted coordinates of an event as strings. Rounded pixels mapped outside of the
+/// sensor are left empty
+fn format_coords_undistorted(
+    coords: (u8, u8),
+    undistortion: &Undistortion,
+    config: &CsvConfig,
+    cam: &CameraParameters,
+) -> (String, String) {
+    if config.round_undistorted {
+        match undistortion.undistort_rounded(coords) {
+            Some(coords) => {
+                let (x, y) = config.transform.apply(coords, cam);
+                (x.to_string(), y.to_string())
+            }
+            None => (String::new(), String::new()),
+        }
+    } else {
+        let (x, y) = config
+            .transform
+            .apply_f32(undistortion.undistort(coords), cam);
+        (format!("{x:.3}"), format!("{y:.3}"))
+    }
 }
 
-fn format_coords_pn(x: u8, y: u8, cam_x: u8) -> String {
-    format!(
-        "{},",
-        ((u32::from(cam_x) * u32::from(y - 1)) + u32::from(x - 1))
-    )
+/// Write the CSV line of an event, with its columns in the order given and timestamps
+/// relative to `time_offset`
+pub fn write_event_line<W: Write>(
+    out: &mut W,
+    event: &Event,
+    columns: &[EventColumn],
+    config: &CsvConfig,
+    cam: &CameraParameters,
+    undistortion: Option<&Undistortion>,
+    time_offset: i32,
+) -> io::Result<()> {
+    let formatting = &config.formatting;
+    let sensor_coords = event.get_coords(&cam.camera_type);
+    let (x, y) = config.transform.apply(sensor_coords, cam);
+
+    let undistorted =
+        undistortion.map(|u| format_coords_undistorted(sensor_coords, u, config, cam));
+
+    for (i, column) in columns.iter().enumerate() {
+        if i > 0 {
+            write!(out, "{}", formatting.delimiter)?;
+        }
+
+        match column {
+            EventColumn::Polarity => write!(
+                out,
+                "{}",
+                format_polarity(event.get_polarity(&cam.camera_type), &formatting.polarity)
+            )?,
+            EventColumn::X => write!(out, "{x}")?,
+            EventColumn::Y => write!(out, "{y}")?,
+            EventColumn::PixelNumber => write!(
+                out,
+                "{}",
+                config
+                    .pixel_numbering
+                    .pixel_number((x, y), config.transform.dimensions(cam))
+            )?,
+            EventColumn::XUndist => write!(out, "{}", undistorted.as_ref().unwrap().0)?,
+            EventColumn::YUndist => write!(out, "{}", undistorted.as_ref().unwrap().1)?,
+            EventColumn::Timestamp => write!(
+                out,
+                "{}",
+                format_timestamp(event.get_timestamp() - time_offset, &formatting.time_unit)
+            )?,
+        }
+    }
+
+    writeln!(out)
 }
 
 pub fn create_csv(
@@ -43,39 +115,39 @@ pub fn create_csv(
     config: &CsvConfig,
     cam: &CameraParameters,
 ) -> std::io::Result<()> {
-    // Create CSV file and write header
-    let mut new_csv = File::create(&config.filename)?;
-    let csv_header = config_csv_header(config);
-    new_csv.write_all(csv_header.as_bytes())?;
+    let formatting = &config.formatting;
+    let columns = config.csv_columns();
+
+    // Create CSV file, compressed if the filename ends in .gz or .zst, and write header
+    let mut new_csv = OutputWriter::create(&config.filename, config.compression)?;
+    if formatting.header {
+        let csv_header = config_csv_header(&columns, formatting.delimiter);
+        new_csv.write_all(csv_header.as_bytes())?;
+    }
 
     // Create write buffer and preallocate space
     const BUF_SIZE: usize = 150_000;
     let mut write_buf = Vec::with_capacity(BUF_SIZE);
 
-    let time_offset = if config.offset_time {
-        events[0].get_timestamp()
-    } else {
-        0
+    let time_offset = match events.first() {
+        Some(event) if config.offset_time => event.get_timestamp(),
+        _ => 0,
     };
 
-    for event in events {
-        let (x, y) = event.get_coords(&cam.camera_type);
-        let event_polarity = event.get_polarity(&cam.camera_type);
+    let undistortion = match &config.calibration {
+        Some(path) => Some(Undistortion::from_calibration_file(path, cam)?),
+        None => None,
+    };
 
-        writeln!(
+    for event in events {
+        write_event_line(
             &mut write_buf,
-            "{p}{xy}{t}",
-            p = if config.include_polarity {
-                format_polarity(event_polarity)
-            } else {
-                String::new()
-            },
-            xy = match config.coords {
-                CoordMode::XY => format_coords_xy(x, y),
-                CoordMode::PixelNum => format_coords_pn(x, y, cam.camera_x),
-                CoordMode::NoCoord => String::new(),
-            },
-            t = event.get_timestamp() - time_offset,
+            &event,
+            &columns,
+            config,
+            cam,
+            undistortion.as_ref(),
+            time_offset,
         )?;
 
         // Write events to disk once enough have been collected
@@ -90,5 +162,5 @@ pub fn create_csv(
         new_csv.write_all(write_buf.as_slice())?;
     }
 
-    Ok(())
+    new_csv.finish()
 }
@@ -0,0 +1,111 @@
+use std::io;
+
+use hdf5::types::VarLenUnicode;
+
+use crate::{
+    aedat_conversions::windows::millisecond_index,
+    aedat_data::{CameraParameters, Event},
+    cli_configs::Hdf5Config,
+};
+
+fn hdf5_error(e: hdf5::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/// Write a chunked and compressed one dimensional dataset
+fn write_dataset<T: hdf5::H5Type>(
+    group: &hdf5::Group,
+    name: &str,
+    data: &[T],
+    config: &Hdf5Config,
+) -> hdf5::Result<()> {
+    // Chunks may not be empty, even when the dataset is
+    let chunk_size = config.chunk_size.min(data.len()).max(1);
+
+    let builder = group.new_dataset_builder().chunk(chunk_size);
+    let builder = if config.compression > 0 {
+        builder.deflate(config.compression)
+    } else {
+        builder
+    };
+
+    builder.with_data(data).create(name)?;
+    Ok(())
+}
+
+fn write_string_attr(location: &hdf5::Group, name: &str, value: &str) -> hdf5::Result<()> {
+    let value: VarLenUnicode = value
+        .parse()
+        .map_err(|_| hdf5::Error::from("Attribute contains a null character"))?;
+    location
+        .new_attr::<VarLenUnicode>()
+        .create(name)?
+        .write_scalar(&value)
+}
+
+/// Export events with the `events/{t,x,y,p}` layout of public event camera datasets.
+///
+/// Timestamps are microseconds relative to the `t_offset` dataset, coordinates are 0-based
+/// and polarities are 1 for ON and 0 for OFF. `ms_to_idx[i]` is the index of the first
+/// event at or after millisecond `i`
+pub fn create_hdf5(
+    events: Vec<Event>,
+    header: &str,
+    config: &Hdf5Config,
+    cam: &CameraParameters,
+) -> io::Result<()> {
+    let t_offset = match events.first() {
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "No events exist",
+            ))
+        }
+        Some(event) => event.get_timestamp(),
+    };
+
+    let mut t = Vec::with_capacity(events.len());
+    let mut x = Vec::with_capacity(events.len());
+    let mut y = Vec::with_capacity(events.len());
+    let mut p = Vec::with_capacity(events.len());
+
+    for event in &events {
+        let (event_x, event_y) = config.transform.event_coords(event, cam);
+
+        t.push(i64::from(event.get_timestamp() - t_offset));
+        x.push(u16::from(event_x - 1));
+        y.push(u16::from(event_y - 1));
+        p.push(u8::from(event.get_polarity(&cam.camera_type)));
+    }
+
+    let ms_to_idx = millisecond_index(&events);
+    let (width, height) = config.transform.dimensions(cam);
+
+    let write = || -> hdf5::Result<()> {
+        let file = hdf5::File::create(&config.filename)?;
+
+        let group = file.create_group("events")?;
+        write_dataset(&group, "t", &t, config)?;
+        write_dataset(&group, "x", &x, config)?;
+        write_dataset(&group, "y", &y, config)?;
+        write_dataset(&group, "p", &p, config)?;
+
+        write_dataset(&file, "ms_to_idx", &ms_to_idx, config)?;
+        file.new_dataset::<i64>()
+            .create("t_offset")?
+            .write_scalar(&i64::from(t_offset))?;
+
+        file.new_attr::<u16>()
+            .create("width")?
+            .write_scalar(&u16::from(width))?;
+        file.new_attr::<u16>()
+            .create("height")?
+            .write_scalar(&u16::from(height))?;
+        write_string_attr(&file, "camera", &format!("{:?}", cam.camera_type))?;
+        write_string_attr(&file, "aedat_header", header)?;
+
+        file.close()
+    };
+
+    write().map_err(hdf5_error)
+}
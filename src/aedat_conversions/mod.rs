@@ -1,3 +1,11 @@
+pub mod columnar;
 pub mod csv;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+pub mod npy_events;
+pub mod rendering;
+pub mod tensor;
+pub mod time_surface;
 pub mod time_window_csv;
 pub mod video;
+pub mod windows;
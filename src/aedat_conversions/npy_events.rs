@@ -0,0 +1,167 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{
+    aedat_data::{CameraParameters, Event},
+    cli_configs::{CoordMode, CsvConfig, EventFormat},
+    npy::{write_npy_header, NpzWriter},
+    undistort::Undistortion,
+};
+
+/// Structured dtype with the same columns as the CSV export, timestamp first,
+/// e.g. `[('t', '<i8'), ('x', '<u2'), ('y', '<u2'), ('p', '<i1')]`
+#[must_use]
+pub fn event_dtype(config: &CsvConfig) -> String {
+    let mut fields = vec![("t", "<i8")];
+
+    match config.coords {
+        CoordMode::NoCoord => (),
+        CoordMode::XY => fields.extend([("x", "<u2"), ("y", "<u2")]),
+        CoordMode::PixelNum => fields.push(("pixel_number", "<u4")),
+    }
+
+    if config.calibration.is_some() {
+        // Rounded coordinates are -1 when they are mapped outside of the sensor
+        if config.round_undistorted {
+            fields.extend([("x_undist", "<i2"), ("y_undist", "<i2")]);
+        } else {
+            fields.extend([("x_undist", "<f4"), ("y_undist", "<f4")]);
+        }
+    }
+
+    if config.include_polarity {
+        fields.push(("p", "<i1"));
+    }
+
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, descr)| format!("('{name}', '{descr}')"))
+        .collect();
+
+    format!("[{}]", fields.join(", "))
+}
+
+/// Append the packed little-endian record of an event to `buf`
+fn write_record(
+    buf: &mut Vec<u8>,
+    event: &Event,
+    config: &CsvConfig,
+    cam: &CameraParameters,
+    undistortion: Option<&Undistortion>,
+    time_offset: i32,
+) {
+    let sensor_coords = event.get_coords(&cam.camera_type);
+    let (x, y) = config.transform.apply(sensor_coords, cam);
+    buf.extend_from_slice(&i64::from(event.get_timestamp() - time_offset).to_le_bytes());
+
+    match config.coords {
+        CoordMode::NoCoord => (),
+        CoordMode::XY => {
+            buf.extend_from_slice(&u16::from(x).to_le_bytes());
+            buf.extend_from_slice(&u16::from(y).to_le_bytes());
+        }
+        CoordMode::PixelNum => {
+            let pixel_number = config
+                .pixel_numbering
+                .pixel_number((x, y), config.transform.dimensions(cam));
+            buf.extend_from_slice(&pixel_number.to_le_bytes());
+        }
+    }
+
+    if let Some(undistortion) = undistortion {
+        if config.round_undistorted {
+            let (x, y) = match undistortion.undistort_rounded(sensor_coords) {
+                Some(coords) => {
+                    let (x, y) = config.transform.apply(coords, cam);
+                    (i16::from(x), i16::from(y))
+                }
+                None => (-1, -1),
+            };
+            buf.extend_from_slice(&x.to_le_bytes());
+            buf.extend_from_slice(&y.to_le_bytes());
+        } else {
+            let (x, y) = config
+                .transform
+                .apply_f32(undistortion.undistort(sensor_coords), cam);
+            buf.extend_from_slice(&x.to_le_bytes());
+            buf.extend_from_slice(&y.to_le_bytes());
+        }
+    }
+
+    if config.include_polarity {
+        let polarity: i8 = if event.get_polarity(&cam.camera_type) {
+            1
+        } else {
+            -1
+        };
+        buf.extend_from_slice(&polarity.to_le_bytes());
+    }
+}
+
+/// Write the events as a structured `.npy` array, streaming the records in chunks
+pub fn write_events_npy<W: Write>(
+    writer: &mut W,
+    events: &[Event],
+    config: &CsvConfig,
+    cam: &CameraParameters,
+) -> io::Result<()> {
+    let time_offset = match events.first() {
+        Some(event) if config.offset_time => event.get_timestamp(),
+        _ => 0,
+    };
+
+    let undistortion = match &config.calibration {
+        Some(path) => Some(Undistortion::from_calibration_file(path, cam)?),
+        None => None,
+    };
+
+    write_npy_header(writer, &event_dtype(config), &[events.len()])?;
+
+    // Create write buffer and preallocate space
+    const BUF_SIZE: usize = 150_000;
+    let mut write_buf = Vec::with_capacity(BUF_SIZE);
+
+    for event in events {
+        write_record(
+            &mut write_buf,
+            event,
+            config,
+            cam,
+            undistortion.as_ref(),
+            time_offset,
+        );
+
+        // Write events once enough have been collected
+        if write_buf.len() >= BUF_SIZE {
+            writer.write_all(&write_buf)?;
+            write_buf.clear();
+        }
+    }
+
+    writer.write_all(&write_buf)
+}
+
+/// Export events as a NumPy structured array to a `.npy` file,
+/// or as the `events` array of a compressed `.npz` archive
+pub fn create_npy_events(
+    events: Vec<Event>,
+    config: &CsvConfig,
+    cam: &CameraParameters,
+) -> io::Result<()> {
+    let file = BufWriter::new(File::create(&config.filename)?);
+
+    match config.format {
+        EventFormat::Npz => {
+            let mut npz = NpzWriter::new(file, true);
+            write_events_npy(npz.start_array("events")?, &events, config, cam)?;
+            npz.finish()?.flush()
+        }
+        _ => {
+            let mut file = file;
+            write_events_npy(&mut file, &events, config, cam)?;
+            file.flush()
+        }
+    }
+}
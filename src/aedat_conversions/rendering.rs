@@ -0,0 +1,449 @@
+use image::{ImageBuffer, Rgb};
+
+use crate::aedat_conversions::time_surface::TimeSurface;
+use crate::aedat_conversions::windows::Window;
+use crate::aedat_data::{CameraParameters, Event};
+use crate::cli_configs::{RenderMode, VidConfig};
+use crate::undistort::Undistortion;
+
+pub type FrameImage = ImageBuffer<Rgb<u8>, Vec<u8>>;
+
+pub mod colors {
+    pub static RED: [u8; 3] = [255u8, 0u8, 0u8];
+    pub static GREEN: [u8; 3] = [0u8, 255u8, 0u8];
+    pub static BLUE: [u8; 3] = [0u8, 0u8, 255u8];
+    pub static BLACK: [u8; 3] = [0u8, 0u8, 0u8];
+    pub static WHITE: [u8; 3] = [255u8, 255u8, 255u8];
+    pub static GREY: [u8; 3] = [128u8, 128u8, 128u8];
+}
+
+/// Colors used to draw ON and OFF events over a background
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub on: Rgb<u8>,
+    pub off: Rgb<u8>,
+    pub background: Rgb<u8>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            on: Rgb(colors::GREEN),
+            off: Rgb(colors::RED),
+            background: Rgb(colors::BLACK),
+        }
+    }
+}
+
+impl Palette {
+    /// Look up a named palette
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Palette> {
+        match name {
+            "green_red" => Some(Palette::default()),
+            "red_blue" => Some(Palette {
+                on: Rgb(colors::RED),
+                off: Rgb(colors::BLUE),
+                background: Rgb(colors::WHITE),
+            }),
+            "white_black" => Some(Palette {
+                on: Rgb(colors::WHITE),
+                off: Rgb(colors::BLACK),
+                background: Rgb(colors::GREY),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a color written as `RRGGBB` or `#RRGGBB`
+pub fn parse_hex_color(hex: &str) -> Result<Rgb<u8>, std::io::Error> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{hex}' is not a hex color of the form #RRGGBB"),
+        )
+    };
+
+    if digits.len() != 6 || !digits.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid());
+
+    Ok(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
+
+/// Scalar colormaps used for count based renderings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Inferno,
+    Grey,
+}
+
+// Colors sampled at evenly spaced points of the matplotlib colormaps
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [31, 12, 72],
+    [85, 15, 109],
+    [136, 34, 106],
+    [186, 54, 85],
+    [227, 89, 51],
+    [249, 140, 10],
+    [249, 201, 50],
+    [252, 255, 164],
+];
+
+impl Colormap {
+    /// Map a value between 0 and 1 to a color, interpolating between the sampled colors
+    #[must_use]
+    pub fn map(&self, value: f32) -> Rgb<u8> {
+        let value = if value.is_nan() {
+            0.0
+        } else {
+            value.clamp(0.0, 1.0)
+        };
+
+        let stops = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+            Colormap::Grey => {
+                let v = (value * 255.0).round() as u8;
+                return Rgb([v, v, v]);
+            }
+        };
+
+        let position = value * (stops.len() - 1) as f32;
+        let lower = (position.floor() as usize).min(stops.len() - 2);
+        let weight = position - lower as f32;
+
+        Rgb(std::array::from_fn(|i| {
+            let (a, b) = (f32::from(stops[lower][i]), f32::from(stops[lower + 1][i]));
+            (a + (b - a) * weight).round() as u8
+        }))
+    }
+}
+
+/// Per-pixel ON and OFF intensities that decay exponentially over time.
+/// Every event adds 1 to the intensity of its polarity at its pixel
+pub struct DecayCanvas {
+    size_x: usize,
+    time_constant: f32,
+    on: Vec<f32>,
+    off: Vec<f32>,
+    last_update: Vec<i32>,
+}
+
+impl DecayCanvas {
+    #[must_use]
+    pub fn new(size_x: usize, size_y: usize, time_constant: u32) -> Self {
+        DecayCanvas {
+            size_x,
+            time_constant: time_constant as f32,
+            on: vec![0.0; size_x * size_y],
+            off: vec![0.0; size_x * size_y],
+            last_update: vec![0; size_x * size_y],
+        }
+    }
+
+    fn decay_factor(&self, index: usize, timestamp: i32) -> f32 {
+        let elapsed = timestamp.saturating_sub(self.last_update[index]).max(0);
+        (-(elapsed as f32) / self.time_constant).exp()
+    }
+
+    /// Add an event at 1-based coordinates
+    pub fn add_event(&mut self, (x, y): (u8, u8), polarity: bool, timestamp: i32) {
+        let index = (usize::from(y - 1) * self.size_x) + usize::from(x - 1);
+        let decay = self.decay_factor(index, timestamp);
+
+        self.on[index] *= decay;
+        self.off[index] *= decay;
+
+        if polarity {
+            self.on[index] += 1.0;
+        } else {
+            self.off[index] += 1.0;
+        }
+
+        self.last_update[index] = timestamp;
+    }
+
+    /// ON and OFF intensities of a 1-based pixel, decayed to the given timestamp
+    #[must_use]
+    pub fn intensity(&self, (x, y): (u8, u8), timestamp: i32) -> (f32, f32) {
+        let index = (usize::from(y - 1) * self.size_x) + usize::from(x - 1);
+        let decay = self.decay_factor(index, timestamp);
+
+        (self.on[index] * decay, self.off[index] * decay)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountNormalization {
+    /// Divide by the largest absolute count of the frame
+    Max,
+    /// Clip absolute counts to the given value and divide by it
+    Clip(u32),
+}
+
+/// Per-pixel event counts of a single frame.
+/// Signed counts add 1 for ON events and subtract 1 for OFF events
+pub struct CountFrame {
+    size_x: usize,
+    signed: bool,
+    counts: Vec<i32>,
+}
+
+impl CountFrame {
+    #[must_use]
+    pub fn new(size_x: usize, size_y: usize, signed: bool) -> Self {
+        CountFrame {
+            size_x,
+            signed,
+            counts: vec![0; size_x * size_y],
+        }
+    }
+
+    /// Add an event at 1-based coordinates
+    pub fn add_event(&mut self, (x, y): (u8, u8), polarity: bool) {
+        let index = (usize::from(y - 1) * self.size_x) + usize::from(x - 1);
+
+        self.counts[index] += if self.signed && !polarity { -1 } else { 1 };
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.fill(0);
+    }
+
+    /// Raw counts in row-major order
+    #[must_use]
+    pub fn counts(&self) -> &[i32] {
+        &self.counts
+    }
+
+    /// Counts scaled to [0, 1], or to [-1, 1] for signed counts
+    #[must_use]
+    pub fn normalized(&self, normalization: CountNormalization) -> Vec<f32> {
+        let scale = match normalization {
+            CountNormalization::Max => self.counts.iter().map(|c| c.unsigned_abs()).max(),
+            CountNormalization::Clip(clip) => Some(clip),
+        }
+        .unwrap_or(0)
+        .max(1) as f32;
+
+        self.counts
+            .iter()
+            .map(|&c| (c as f32 / scale).clamp(-1.0, 1.0))
+            .collect()
+    }
+}
+
+/// Mix the ON and OFF colors into the background by their intensities
+fn blend(palette: &Palette, (on, off): (f32, f32)) -> Rgb<u8> {
+    let (on, off) = (on.min(1.0), off.min(1.0));
+
+    Rgb(std::array::from_fn(|i| {
+        let background = f32::from(palette.background[i]);
+        let on_delta = f32::from(palette.on[i]) - background;
+        let off_delta = f32::from(palette.off[i]) - background;
+
+        (background + on_delta * on + off_delta * off).clamp(0.0, 255.0) as u8
+    }))
+}
+
+/// Time span of the window of a single frame and its number of events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameInfo {
+    pub start_time: i32,
+    pub end_time: i32,
+    pub event_count: usize,
+}
+
+impl From<&Window> for FrameInfo {
+    fn from(window: &Window) -> Self {
+        FrameInfo {
+            start_time: window.start_time,
+            end_time: window.end_time,
+            event_count: window.events.len(),
+        }
+    }
+}
+
+impl FrameInfo {
+    /// Event rate in events per second, if the frame spans any time
+    #[must_use]
+    pub fn event_rate(&self) -> Option<f64> {
+        let duration = self.end_time - self.start_time;
+        (duration > 0).then(|| self.event_count as f64 * 1_000_000.0 / f64::from(duration))
+    }
+}
+
+enum Canvas {
+    LastEvent(FrameImage),
+    Decay(DecayCanvas),
+    TimeSurface(TimeSurface, u32),
+    Counts(CountFrame, CountNormalization),
+}
+
+/// Accumulates events into video frames according to the configured render mode
+pub struct FrameRenderer<'a> {
+    config: &'a VidConfig,
+    cam: &'a CameraParameters,
+    undistortion: Option<&'a Undistortion>,
+    canvas: Canvas,
+    width: u8,
+    height: u8,
+}
+
+impl<'a> FrameRenderer<'a> {
+    #[must_use]
+    pub fn new(
+        config: &'a VidConfig,
+        cam: &'a CameraParameters,
+        undistortion: Option<&'a Undistortion>,
+    ) -> Self {
+        let (width, height) = config.transform.dimensions(cam);
+
+        let canvas = match config.render_mode {
+            RenderMode::LastEvent => Canvas::LastEvent(ImageBuffer::from_pixel(
+                u32::from(width),
+                u32::from(height),
+                config.palette.background,
+            )),
+            RenderMode::Decay { time_constant } => Canvas::Decay(DecayCanvas::new(
+                usize::from(width),
+                usize::from(height),
+                time_constant,
+            )),
+            RenderMode::TimeSurface { time_constant } => Canvas::TimeSurface(
+                TimeSurface::new(usize::from(width), usize::from(height)),
+                time_constant,
+            ),
+            RenderMode::Counts {
+                signed,
+                normalization,
+            } => Canvas::Counts(
+                CountFrame::new(usize::from(width), usize::from(height), signed),
+                normalization,
+            ),
+        };
+
+        FrameRenderer {
+            config,
+            cam,
+            undistortion,
+            canvas,
+            width,
+            height,
+        }
+    }
+
+    pub fn add_event(&mut self, event: &Event) {
+        let coords = event.get_coords(&self.cam.camera_type);
+        let timestamp = event.get_timestamp();
+
+        // Events that are undistorted outside of the sensor are dropped
+        let (x, y) = match self.undistortion.map(|u| u.undistort_rounded(coords)) {
+            Some(Some(coords)) => self.config.transform.apply(coords, self.cam),
+            Some(None) => return,
+            None => self.config.transform.apply(coords, self.cam),
+        };
+
+        let event_polarity = event.get_polarity(&self.cam.camera_type);
+
+        if (self.config.exclude_on && event_polarity)
+            || (self.config.exclude_off && !event_polarity)
+        {
+            return;
+        }
+
+        match &mut self.canvas {
+            Canvas::LastEvent(img) => {
+                let color = if event_polarity {
+                    self.config.palette.on
+                } else {
+                    self.config.palette.off
+                };
+                img.put_pixel(u32::from(x - 1), u32::from(y - 1), color);
+            }
+            Canvas::Decay(decay) => decay.add_event((x, y), event_polarity, timestamp),
+            Canvas::TimeSurface(surface, _) => surface.add_event((x, y), event_polarity, timestamp),
+            Canvas::Counts(counts, _) => counts.add_event((x, y), event_polarity),
+        }
+    }
+
+    /// Render the current state of the canvas. Decaying canvases and time surfaces are
+    /// evaluated at `time`, the end of the frame's window
+    #[must_use]
+    pub fn render(&self, time: i32) -> FrameImage {
+        match &self.canvas {
+            Canvas::LastEvent(img) => img.clone(),
+            Canvas::Decay(decay) => {
+                ImageBuffer::from_fn(u32::from(self.width), u32::from(self.height), |x, y| {
+                    let intensity = decay.intensity((x as u8 + 1, y as u8 + 1), time);
+                    blend(&self.config.palette, intensity)
+                })
+            }
+            Canvas::TimeSurface(surface, time_constant) => {
+                ImageBuffer::from_fn(u32::from(self.width), u32::from(self.height), |x, y| {
+                    let value = surface.value((x as u8 + 1, y as u8 + 1), time, *time_constant);
+                    blend(&self.config.palette, value)
+                })
+            }
+            Canvas::Counts(counts, normalization) => {
+                let values = counts.normalized(*normalization);
+                let width = u32::from(self.width);
+
+                // Unsigned counts use the colormap; signed counts use the palette
+                // with ON counts above and OFF counts below the background
+                ImageBuffer::from_fn(width, u32::from(self.height), |x, y| {
+                    let value = values[(y * width + x) as usize];
+
+                    if counts.signed {
+                        blend(&self.config.palette, (value.max(0.0), (-value).max(0.0)))
+                    } else {
+                        self.config.colormap.map(value)
+                    }
+                })
+            }
+        }
+    }
+
+    /// Raw per-pixel counts of the current frame when rendering counts
+    #[must_use]
+    pub fn counts(&self) -> Option<&[i32]> {
+        match &self.canvas {
+            Canvas::Counts(counts, _) => Some(counts.counts()),
+            _ => None,
+        }
+    }
+
+    /// Prepare the canvas for the next frame.
+    /// Decaying canvases and time surfaces carry their state over between frames
+    pub fn next_frame(&mut self) {
+        match &mut self.canvas {
+            Canvas::LastEvent(img) => {
+                for pixel in img.pixels_mut() {
+                    *pixel = self.config.palette.background;
+                }
+            }
+            Canvas::Counts(counts, _) => counts.reset(),
+            Canvas::Decay(_) | Canvas::TimeSurface(..) => (),
+        }
+    }
+}
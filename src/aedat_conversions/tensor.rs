@@ -0,0 +1,175 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+};
+
+use crate::{
+    aedat_conversions::windows::{split_windows, Window},
+    aedat_data::{CameraParameters, Event},
+    cli_configs::{TensorConfig, TensorFormat, TensorKind, WindowMode},
+    npy::{write_npy, NpzWriter},
+    transform::Transform,
+};
+
+/// Voxel grid with `bins` temporal bins. Each event adds its polarity (+1 or -1)
+/// to the two bins closest to its normalized timestamp, weighted linearly
+#[must_use]
+pub fn voxel_grid(
+    events: &[Event],
+    window: &Window,
+    bins: usize,
+    transform: &Transform,
+    cam: &CameraParameters,
+) -> Vec<f32> {
+    let (width, height) = transform.dimensions(cam);
+    let (width, height) = (usize::from(width), usize::from(height));
+    let mut grid = vec![0.0f32; bins * width * height];
+
+    let duration = (window.end_time - window.start_time) as f32;
+
+    for event in &events[window.events.clone()] {
+        let (x, y) = transform.event_coords(event, cam);
+        let pixel = (usize::from(y - 1) * width) + usize::from(x - 1);
+
+        let polarity = if event.get_polarity(&cam.camera_type) {
+            1.0
+        } else {
+            -1.0
+        };
+
+        let t = if duration > 0.0 {
+            (bins - 1) as f32 * (event.get_timestamp() - window.start_time) as f32 / duration
+        } else {
+            0.0
+        };
+
+        let lower = (t.floor() as usize).min(bins - 1);
+        let weight = t - lower as f32;
+
+        grid[(lower * width * height) + pixel] += polarity * (1.0 - weight);
+        if lower + 1 < bins {
+            grid[((lower + 1) * width * height) + pixel] += polarity * weight;
+        }
+    }
+
+    grid
+}
+
+/// Two channel event count histogram with the ON channel first
+#[must_use]
+pub fn histogram(
+    events: &[Event],
+    window: &Window,
+    transform: &Transform,
+    cam: &CameraParameters,
+) -> Vec<f32> {
+    let (width, height) = transform.dimensions(cam);
+    let (width, height) = (usize::from(width), usize::from(height));
+    let mut counts = vec![0.0f32; 2 * width * height];
+
+    for event in &events[window.events.clone()] {
+        let (x, y) = transform.event_coords(event, cam);
+        let pixel = (usize::from(y - 1) * width) + usize::from(x - 1);
+
+        if event.get_polarity(&cam.camera_type) {
+            counts[pixel] += 1.0;
+        } else {
+            counts[(width * height) + pixel] += 1.0;
+        }
+    }
+
+    counts
+}
+
+pub fn create_tensors(
+    events: Vec<Event>,
+    config: &TensorConfig,
+    cam: &CameraParameters,
+) -> io::Result<()> {
+    let mut windows = split_windows(&events, &config.window_mode, config.window_size);
+
+    if windows.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No events exist",
+        ));
+    }
+
+    // The recording ends partway through the last time window, so it would not cover
+    // window_size microseconds like the others
+    if let WindowMode::Time = config.window_mode {
+        windows.pop();
+        if windows.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Recording is shorter than one window",
+            ));
+        }
+    }
+    windows.truncate(config.max_windows);
+
+    let (width, height) = config.transform.dimensions(cam);
+    let (width, height) = (usize::from(width), usize::from(height));
+
+    let channels = match config.kind {
+        TensorKind::VoxelGrid { bins } => bins,
+        TensorKind::Histogram => 2,
+    };
+    let shape = [channels, height, width];
+
+    let tensors = windows.iter().map(|window| match config.kind {
+        TensorKind::VoxelGrid { bins } => voxel_grid(&events, window, bins, &config.transform, cam),
+        TensorKind::Histogram => histogram(&events, window, &config.transform, cam),
+    });
+
+    match config.format {
+        TensorFormat::Npy => {
+            // One array per window, with an index of the window timestamps
+            let output_dir = &config.filename;
+            if let Err(e) = fs::create_dir(output_dir) {
+                if e.kind() != ErrorKind::AlreadyExists {
+                    return Err(e);
+                }
+            }
+
+            for (i, tensor) in tensors.enumerate() {
+                let mut writer =
+                    BufWriter::new(File::create(output_dir.join(format!("tensor_{i}.npy")))?);
+                write_npy(&mut writer, &shape, &tensor)?;
+            }
+
+            let mut index = BufWriter::new(File::create(output_dir.join("index.csv"))?);
+            writeln!(index, "Tensor,Start,End,Events")?;
+            for (i, window) in windows.iter().enumerate() {
+                writeln!(
+                    index,
+                    "{i},{},{},{}",
+                    window.start_time,
+                    window.end_time,
+                    window.events.len()
+                )?;
+            }
+            index.flush()?;
+        }
+        TensorFormat::Npz { compressed } => {
+            let file = BufWriter::new(File::create(&config.filename)?);
+            let mut npz = NpzWriter::new(file, compressed);
+
+            for (i, tensor) in tensors.enumerate() {
+                npz.add_array(&format!("tensor_{i}"), &shape, &tensor)?;
+            }
+
+            let start_times: Vec<i64> = windows.iter().map(|w| i64::from(w.start_time)).collect();
+            let end_times: Vec<i64> = windows.iter().map(|w| i64::from(w.end_time)).collect();
+            let event_counts: Vec<i64> = windows.iter().map(|w| w.events.len() as i64).collect();
+
+            npz.add_array("start_times", &[windows.len()], &start_times)?;
+            npz.add_array("end_times", &[windows.len()], &end_times)?;
+            npz.add_array("event_counts", &[windows.len()], &event_counts)?;
+
+            npz.finish()?.flush()?;
+        }
+    }
+
+    Ok(())
+}
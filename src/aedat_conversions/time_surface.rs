@@ -0,0 +1,168 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind},
+    path::Path,
+};
+
+use image::{ImageBuffer, Luma};
+
+use crate::{
+    aedat_data::{CameraParameters, Event},
+    cli_configs::{SurfaceFormat, TimeSurfaceConfig},
+    npy::write_npy,
+};
+
+/// Surface of Active Events: the latest timestamp of each pixel, per polarity
+pub struct TimeSurface {
+    size_x: usize,
+    size_y: usize,
+    latest_on: Vec<Option<i32>>,
+    latest_off: Vec<Option<i32>>,
+}
+
+impl TimeSurface {
+    #[must_use]
+    pub fn new(size_x: usize, size_y: usize) -> Self {
+        TimeSurface {
+            size_x,
+            size_y,
+            latest_on: vec![None; size_x * size_y],
+            latest_off: vec![None; size_x * size_y],
+        }
+    }
+
+    #[must_use]
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.size_x, self.size_y)
+    }
+
+    /// Record an event at 1-based coordinates
+    pub fn add_event(&mut self, (x, y): (u8, u8), polarity: bool, timestamp: i32) {
+        let index = (usize::from(y - 1) * self.size_x) + usize::from(x - 1);
+
+        if polarity {
+            self.latest_on[index] = Some(timestamp);
+        } else {
+            self.latest_off[index] = Some(timestamp);
+        }
+    }
+
+    fn decay(latest: Option<i32>, timestamp: i32, time_constant: f32) -> f32 {
+        match latest {
+            Some(t) => (-(timestamp.saturating_sub(t).max(0) as f32) / time_constant).exp(),
+            None => 0.0,
+        }
+    }
+
+    /// ON and OFF values of a 1-based pixel, exponentially decayed to the given timestamp
+    #[must_use]
+    pub fn value(&self, (x, y): (u8, u8), timestamp: i32, time_constant: u32) -> (f32, f32) {
+        let index = (usize::from(y - 1) * self.size_x) + usize::from(x - 1);
+        let time_constant = time_constant as f32;
+
+        (
+            Self::decay(self.latest_on[index], timestamp, time_constant),
+            Self::decay(self.latest_off[index], timestamp, time_constant),
+        )
+    }
+
+    /// Sample the whole surface as a 2 x height x width array with the ON channel first
+    #[must_use]
+    pub fn sample(&self, timestamp: i32, time_constant: u32) -> Vec<f32> {
+        let time_constant = time_constant as f32;
+
+        self.latest_on
+            .iter()
+            .chain(self.latest_off.iter())
+            .map(|&latest| Self::decay(latest, timestamp, time_constant))
+            .collect()
+    }
+}
+
+fn write_sample(
+    sample: &[f32],
+    (size_x, size_y): (usize, usize),
+    output_dir: &Path,
+    index: usize,
+    format: &SurfaceFormat,
+) -> io::Result<()> {
+    match format {
+        SurfaceFormat::Npy => {
+            let path = output_dir.join(format!("surface_{index}.npy"));
+            let mut writer = BufWriter::new(File::create(path)?);
+            write_npy(&mut writer, &[2, size_y, size_x], sample)
+        }
+        SurfaceFormat::Png => {
+            for (channel, suffix) in sample.chunks(size_x * size_y).zip(["on", "off"]) {
+                let pixels = channel
+                    .iter()
+                    .map(|v| (v * f32::from(u16::MAX)).round() as u16)
+                    .collect();
+
+                let img: ImageBuffer<Luma<u16>, Vec<u16>> =
+                    ImageBuffer::from_raw(size_x as u32, size_y as u32, pixels).unwrap();
+
+                img.save(output_dir.join(format!("surface_{index}_{suffix}.png")))
+                    .map_err(io::Error::other)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn create_time_surfaces(
+    events: Vec<Event>,
+    config: &TimeSurfaceConfig,
+    cam: &CameraParameters,
+) -> io::Result<()> {
+    if let Err(e) = fs::create_dir(&config.filename) {
+        if e.kind() != ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+    }
+
+    // Define the first sample time relative to the first event
+    let mut sample_time = match events.first() {
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "No events exist",
+            ))
+        }
+        Some(event) => event.get_timestamp() + config.interval as i32,
+    };
+
+    let (width, height) = config.transform.dimensions(cam);
+    let mut surface = TimeSurface::new(usize::from(width), usize::from(height));
+    let mut surfaces_created = 0;
+
+    'events: for event in events {
+        // Sample every interval that ended before this event, including intervals without events
+        while event.get_timestamp() > sample_time {
+            let sample = surface.sample(sample_time, config.time_constant);
+            write_sample(
+                &sample,
+                surface.dimensions(),
+                &config.filename,
+                surfaces_created,
+                &config.format,
+            )?;
+
+            sample_time += config.interval as i32;
+
+            surfaces_created += 1;
+            if surfaces_created == config.max_surfaces {
+                break 'events;
+            }
+        }
+
+        let coords = config.transform.event_coords(&event, cam);
+        surface.add_event(
+            coords,
+            event.get_polarity(&cam.camera_type),
+            event.get_timestamp(),
+        );
+    }
+
+    Ok(())
+}
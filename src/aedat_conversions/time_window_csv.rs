@@ -1,11 +1,17 @@
 use std::{
-    fs::File,
-    io::{self, Write},
+    fs,
+    io::{self, ErrorKind, Write},
+    path::Path,
 };
 
+use image::{ImageBuffer, Luma};
+
 use crate::{
+    aedat_conversions::windows::{sliding_windows, split_windows, Window},
     aedat_data::{CameraParameters, Event},
-    cli_configs::TimeWindowConfig,
+    cli_configs::{PgmOutput, TimeWindowConfig, WindowColumn, WindowMode},
+    compression::{strip_compression_extension, OutputWriter},
+    transform::Transform,
 };
 
 pub struct Downres {
@@ -31,6 +37,7 @@ impl Downres {
         }
     }
 
+    #[allow(dead_code)]
     pub fn reset(&mut self) {
         self.pixels = vec![0; self.size_x_downscaled * self.size_y_downscaled];
     }
@@ -61,18 +68,58 @@ impl Downres {
         }
     }
 
-    pub fn to_pgm(&self) -> String {
+    pub fn decrement_pixel(&mut self, x: usize, y: usize) -> Result<(), std::io::Error> {
+        if x > 0 && x <= self.size_x && y > 0 && y <= self.size_y {
+            self.pixels
+                [(((y - 1) / self.scale) * self.size_x_downscaled) + ((x - 1) / self.scale)] -= 1;
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Index out of bounds",
+            ))
+        }
+    }
+
+    /// Grey level of each downscaled pixel and the maximum grey level: either 0 or 1 for the
+    /// thresholded image, or the event count of pixels that reach the threshold
+    #[must_use]
+    pub fn grey_levels(&self, counts: bool) -> (Vec<u16>, u16) {
+        let levels: Vec<u16> = self
+            .pixels
+            .iter()
+            .map(|&val| match (val >= self.threshold, counts) {
+                (false, _) => 0,
+                (true, false) => 1,
+                (true, true) => u16::try_from(val).unwrap_or(u16::MAX),
+            })
+            .collect();
+
+        let max_level = if counts {
+            levels.iter().copied().max().unwrap_or(0).max(1)
+        } else {
+            1
+        };
+
+        (levels, max_level)
+    }
+
+    /// ASCII (`P2`) PGM image
+    #[must_use]
+    pub fn to_ascii_pgm(&self, counts: bool) -> String {
+        let (levels, max_level) = self.grey_levels(counts);
+
         let mut result = String::from(&format!(
-            "P2\n{} {}\n1\n",
-            self.size_x_downscaled, self.size_y_downscaled
+            "P2\n{} {}\n{}\n",
+            self.size_x_downscaled, self.size_y_downscaled, max_level
         ));
 
         let pgm_width = self.size_x_downscaled;
 
-        for (i, val) in self.pixels.iter().enumerate() {
+        for (i, val) in levels.iter().enumerate() {
             result.push_str(&format!(
                 "{}{}",
-                i32::from(val >= &self.threshold),
+                val,
                 if i % pgm_width == pgm_width - 1 {
                     "\n"
                 } else {
@@ -83,6 +130,204 @@ impl Downres {
 
         result
     }
+
+    /// Binary (`P5`) PGM image, with two bytes per pixel when the grey levels exceed 255
+    #[must_use]
+    pub fn to_binary_pgm(&self, counts: bool) -> Vec<u8> {
+        let (levels, max_level) = self.grey_levels(counts);
+
+        let mut result = format!(
+            "P5\n{} {}\n{}\n",
+            self.size_x_downscaled, self.size_y_downscaled, max_level
+        )
+        .into_bytes();
+
+        for val in levels {
+            if max_level > u16::from(u8::MAX) {
+                result.extend_from_slice(&val.to_be_bytes());
+            } else {
+                result.push(val as u8);
+            }
+        }
+
+        result
+    }
+
+    /// Save the image as a PNG. Thresholded images are black and white;
+    /// counts are stored unscaled in a 16-bit image
+    pub fn save_png(&self, path: &Path, counts: bool) -> io::Result<()> {
+        let (levels, _) = self.grey_levels(counts);
+        let (width, height) = (self.size_x_downscaled as u32, self.size_y_downscaled as u32);
+
+        if counts {
+            let img: ImageBuffer<Luma<u16>, Vec<u16>> =
+                ImageBuffer::from_raw(width, height, levels).unwrap();
+            img.save(path).map_err(io::Error::other)
+        } else {
+            let pixels = levels.iter().map(|&val| val as u8 * u8::MAX).collect();
+            let img: ImageBuffer<Luma<u8>, Vec<u8>> =
+                ImageBuffer::from_raw(width, height, pixels).unwrap();
+            img.save(path).map_err(io::Error::other)
+        }
+    }
+}
+
+/// Back-to-back windows of `window_size` microseconds. A window ends at the first event
+/// after its end time, and the trailing window that is still open when the events run out
+/// is not included
+fn disjoint_windows(events: &[Event], window_size: u32) -> Vec<Window> {
+    let mut windows = Vec::new();
+
+    let Some(first) = events.first() else {
+        return windows;
+    };
+
+    let mut end_time = first.get_timestamp() + window_size as i32;
+    let mut start = 0;
+
+    for (i, event) in events.iter().enumerate() {
+        if event.get_timestamp() > end_time {
+            windows.push(Window {
+                events: start..i,
+                start_time: end_time - window_size as i32,
+                end_time,
+            });
+
+            start = i;
+            end_time += window_size as i32;
+        }
+    }
+
+    windows
+}
+
+/// Running totals of the events in the current window, updated as events enter and leave it
+pub struct WindowStats {
+    on: usize,
+    off: usize,
+    size_x: usize,
+    pixel_counts: Vec<u32>,
+    active_pixels: usize,
+    sum_x: u64,
+    sum_y: u64,
+    sum_x_squared: u64,
+    sum_y_squared: u64,
+    downres: Option<Downres>,
+}
+
+impl WindowStats {
+    #[must_use]
+    pub fn new(size_x: usize, size_y: usize, downres: Option<Downres>) -> Self {
+        WindowStats {
+            on: 0,
+            off: 0,
+            size_x,
+            pixel_counts: vec![0; size_x * size_y],
+            active_pixels: 0,
+            sum_x: 0,
+            sum_y: 0,
+            sum_x_squared: 0,
+            sum_y_squared: 0,
+            downres,
+        }
+    }
+
+    /// Add an event entering the window, or remove an event leaving it
+    pub fn update(
+        &mut self,
+        event: &Event,
+        entering: bool,
+        transform: &Transform,
+        cam: &CameraParameters,
+    ) -> io::Result<()> {
+        let (x, y) = transform.event_coords(event, cam);
+        let (x, y) = (u64::from(x), u64::from(y));
+        let pixel = ((y as usize - 1) * self.size_x) + (x as usize - 1);
+
+        let count = if event.get_polarity(&cam.camera_type) {
+            &mut self.on
+        } else {
+            &mut self.off
+        };
+
+        if entering {
+            *count += 1;
+
+            self.pixel_counts[pixel] += 1;
+            if self.pixel_counts[pixel] == 1 {
+                self.active_pixels += 1;
+            }
+
+            self.sum_x += x;
+            self.sum_y += y;
+            self.sum_x_squared += x * x;
+            self.sum_y_squared += y * y;
+        } else {
+            *count -= 1;
+
+            self.pixel_counts[pixel] -= 1;
+            if self.pixel_counts[pixel] == 0 {
+                self.active_pixels -= 1;
+            }
+
+            self.sum_x -= x;
+            self.sum_y -= y;
+            self.sum_x_squared -= x * x;
+            self.sum_y_squared -= y * y;
+        }
+
+        if let Some(downres) = &mut self.downres {
+            if entering {
+                downres.increment_pixel(x as usize, y as usize)?;
+            } else {
+                downres.decrement_pixel(x as usize, y as usize)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Value of a column for the given window. Statistics that are undefined,
+    /// e.g. the centroid of a window without events, are left empty
+    #[must_use]
+    pub fn value(&self, column: WindowColumn, window: &Window, events: &[Event]) -> String {
+        let total = self.on + self.off;
+        let n = total as f64;
+        let duration = window.end_time - window.start_time;
+
+        match column {
+            WindowColumn::Start => window.start_time.to_string(),
+            WindowColumn::End => window.end_time.to_string(),
+            WindowColumn::Duration => duration.to_string(),
+            WindowColumn::On => self.on.to_string(),
+            WindowColumn::Off => self.off.to_string(),
+            WindowColumn::Both => total.to_string(),
+            WindowColumn::ActivePixels => self.active_pixels.to_string(),
+            WindowColumn::EventRate if duration > 0 => {
+                format!("{:.3}", n * 1_000_000.0 / f64::from(duration))
+            }
+            WindowColumn::PolarityRatio if total > 0 => format!("{:.3}", self.on as f64 / n),
+            WindowColumn::CentroidX if total > 0 => format!("{:.3}", self.sum_x as f64 / n),
+            WindowColumn::CentroidY if total > 0 => format!("{:.3}", self.sum_y as f64 / n),
+            WindowColumn::Spread if total > 0 => {
+                // Root mean square distance of the events from the centroid
+                let mean_x = self.sum_x as f64 / n;
+                let mean_y = self.sum_y as f64 / n;
+                let variance = (self.sum_x_squared as f64 / n - mean_x * mean_x)
+                    + (self.sum_y_squared as f64 / n - mean_y * mean_y);
+                format!("{:.3}", variance.max(0.0).sqrt())
+            }
+            WindowColumn::MeanInterval if window.events.len() > 1 => {
+                let first = events[window.events.start].get_timestamp();
+                let last = events[window.events.end - 1].get_timestamp();
+                format!(
+                    "{:.3}",
+                    f64::from(last - first) / (window.events.len() - 1) as f64
+                )
+            }
+            _ => String::new(),
+        }
+    }
 }
 
 pub fn create_time_window_csv(
@@ -90,8 +335,15 @@ pub fn create_time_window_csv(
     config: &TimeWindowConfig,
     cam: &CameraParameters,
 ) -> std::io::Result<()> {
-    // Create CSV file and write header
-    let mut new_csv = File::create(&config.filename)?;
+    if events.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "No events exist",
+        ));
+    }
+
+    // Create CSV file, compressed if the filename ends in .gz or .zst, and write header
+    let mut new_csv = OutputWriter::create(&config.filename, config.compression)?;
     let csv_header = config.create_csv_header();
     new_csv.write_all(csv_header.as_bytes())?;
 
@@ -99,74 +351,104 @@ pub fn create_time_window_csv(
     const BUF_SIZE: usize = 150_000;
     let mut write_buf = Vec::with_capacity(BUF_SIZE);
 
-    let mut end_time = match events.first() {
-        None => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "No events exist",
-            ))
+    let window_size = config.window_size as usize;
+    let mut windows = match (&config.window_mode, config.stride) {
+        (WindowMode::EventCount, _) => {
+            // Like time windows, a trailing window that is not complete is not included
+            let mut windows = split_windows(&events, &config.window_mode, window_size);
+            windows.retain(|window| window.events.len() == window_size);
+            windows
         }
-        Some(event) => event.get_timestamp() + config.window_size as i32,
+        (WindowMode::Time, Some(stride)) => sliding_windows(
+            &events,
+            window_size,
+            stride as usize,
+            config.max_windows as usize,
+        ),
+        (WindowMode::Time, None) => disjoint_windows(&events, config.window_size),
     };
-
-    let mut on_count = 0;
-    let mut off_count = 0;
-    let mut windows_created = 0;
+    windows.truncate(config.max_windows as usize);
 
     // Downscaled PGM image
-    let mut downres = Downres::new(
-        cam.camera_x as usize,
-        cam.camera_y as usize,
-        config.pgm_scale,
-        config.pgm_threshold,
-    );
-
-    for event in events {
-        if event.get_timestamp() > end_time {
-            writeln!(
-                &mut write_buf,
-                "{on},{off}{both}{downres_pgm}",
-                on = on_count,
-                off = off_count,
-                both = if config.include_both_column {
-                    format!(",{}", on_count + off_count)
-                } else {
-                    String::new()
-                },
-                downres_pgm = if config.include_pgm {
-                    format!(",{}", downres.to_pgm().replace('\n', "-"))
-                } else {
-                    String::new()
+    let (width, height) = config.transform.dimensions(cam);
+    let downres = config.include_pgm.then(|| {
+        Downres::new(
+            width as usize,
+            height as usize,
+            config.pgm_scale,
+            config.pgm_threshold,
+        )
+    });
+    let mut stats = WindowStats::new(width as usize, height as usize, downres);
+
+    // Image files are written next to the CSV, which references them by relative path
+    let image_dir = match config.pgm_output {
+        PgmOutput::String => None,
+        PgmOutput::Pgm | PgmOutput::Png if config.include_pgm => {
+            let mut image_dir = strip_compression_extension(&config.filename).with_extension("");
+            image_dir.as_mut_os_string().push("_windows");
+            if let Err(e) = fs::create_dir(&image_dir) {
+                if e.kind() != ErrorKind::AlreadyExists {
+                    return Err(e);
                 }
-            )?;
+            }
+            Some(image_dir)
+        }
+        PgmOutput::Pgm | PgmOutput::Png => None,
+    };
 
-            downres.reset();
+    // Windows only move forward, so each event enters and leaves the running counts once
+    let mut current = 0..0;
 
-            // Write events to disk once enough have been collected
-            if write_buf.len() >= BUF_SIZE {
-                new_csv.write_all(write_buf.as_slice())?;
-                write_buf.clear();
-            }
+    for (i, window) in windows.into_iter().enumerate() {
+        let leaving = current.start..window.events.start.min(current.end);
+        let entering = window.events.start.max(current.end)..window.events.end;
+
+        for event in &events[leaving] {
+            stats.update(event, false, &config.transform, cam)?;
+        }
+        for event in &events[entering] {
+            stats.update(event, true, &config.transform, cam)?;
+        }
+        current = window.events.clone();
 
-            end_time += config.window_size as i32;
-            on_count = 0;
-            off_count = 0;
+        let row = config
+            .columns
+            .iter()
+            .map(|&column| stats.value(column, &window, &events))
+            .collect::<Vec<_>>()
+            .join(",");
 
-            windows_created += 1;
-            if windows_created == config.max_windows {
-                break;
+        let downres_pgm = match (&stats.downres, &image_dir) {
+            (Some(downres), Some(image_dir)) => {
+                let extension = match config.pgm_output {
+                    PgmOutput::Png => "png",
+                    _ => "pgm",
+                };
+                let image_name = format!("window_{i}.{extension}");
+                let path = image_dir.join(&image_name);
+
+                match config.pgm_output {
+                    PgmOutput::Png => downres.save_png(&path, config.pgm_counts)?,
+                    _ => fs::write(&path, downres.to_binary_pgm(config.pgm_counts))?,
+                }
+
+                let dir_name = image_dir.file_name().unwrap().to_string_lossy();
+                format!(",{dir_name}/{image_name}")
             }
-        }
+            (Some(downres), None) => format!(
+                ",{}",
+                downres.to_ascii_pgm(config.pgm_counts).replace('\n', "-")
+            ),
+            (None, _) => String::new(),
+        };
 
-        if event.get_polarity(&cam.camera_type) {
-            on_count += 1;
-        } else {
-            off_count += 1;
-        }
+        writeln!(&mut write_buf, "{row}{downres_pgm}")?;
 
-        if config.include_pgm {
-            let (x, y) = event.get_coords(&cam.camera_type);
-            downres.increment_pixel(x as usize, y as usize)?;
+        // Write events to disk once enough have been collected
+        if write_buf.len() >= BUF_SIZE {
+            new_csv.write_all(write_buf.as_slice())?;
+            write_buf.clear();
         }
     }
 
@@ -175,5 +457,5 @@ pub fn create_time_window_csv(
         new_csv.write_all(write_buf.as_slice())?;
     }
 
-    Ok(())
+    new_csv.finish()
 }
@@ -1,60 +1,40 @@
-use std::io::Error;
 use std::{
-    fs,
-    io::ErrorKind,
+    fs::{self, File},
+    io::{BufWriter, Error, ErrorKind, Write},
+    ops::Range,
     path::{Path, PathBuf},
 };
 
-use image::{ImageBuffer, Rgb};
-use opencv::core::Size;
-use opencv::imgcodecs::{imread, IMREAD_COLOR};
-
+use image::imageops::{self, FilterType};
+use opencv::core::{Mat, Point, Scalar, Size, Vector};
+use opencv::imgcodecs::imwrite;
+use opencv::imgproc::{self, cvt_color, COLOR_RGB2BGR, FONT_HERSHEY_PLAIN, LINE_AA};
 use opencv::prelude::*;
 use opencv::videoio::VideoWriter;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
+use crate::aedat_conversions::rendering::{FrameImage, FrameInfo, FrameRenderer};
+use crate::aedat_conversions::windows::{sliding_windows, Window};
 use crate::aedat_data::{CameraParameters, Event};
-use crate::cli_configs::VidConfig;
-
-mod colors {
-    pub static RED: [u8; 3] = [255u8, 0u8, 0u8];
-    pub static GREEN: [u8; 3] = [0u8, 255u8, 0u8];
-    pub static BLACK: [u8; 3] = [0u8, 0u8, 0u8];
-}
+use crate::cli_configs::{RenderMode, VidConfig};
+use crate::npy::NpzWriter;
+use crate::undistort::Undistortion;
 
-pub struct Frame {
-    pub img: ImageBuffer<Rgb<u8>, Vec<u8>>,
-    pub count: usize,
+fn cv_error(e: opencv::Error) -> Error {
+    Error::other(e.to_string())
 }
 
-impl Frame {
-    pub fn save_frame(&self, frame_tmp_dir: &Path, filename: &str) -> std::io::Result<()> {
-        let result = self.img.save(format!(
-            "{}/{}_frame{}.png",
-            frame_tmp_dir.to_string_lossy(),
-            filename,
-            self.count
-        ));
-
-        if let Ok(()) = result {
-            Ok(())
-        } else {
-            Err(Error::new(ErrorKind::Other, "Could not save frame"))
-        }
-
-        // Ok(())
-    }
-}
-
-fn prep_frame_tmp_dir(tmp_dir: &PathBuf) -> std::io::Result<()> {
-    // Create frame tmp directory if it does not exist
-    if let Err(e) = fs::create_dir(tmp_dir) {
+fn prep_frame_dir(frame_dir: &PathBuf) -> std::io::Result<()> {
+    // Create frame directory if it does not exist
+    if let Err(e) = fs::create_dir(frame_dir) {
         if e.kind() != ErrorKind::AlreadyExists {
             return Err(e);
         }
     }
 
     // Clear any old files
-    let paths = fs::read_dir(tmp_dir)?;
+    let paths = fs::read_dir(frame_dir)?;
     for path in paths {
         fs::remove_file(path?.path())?;
     }
@@ -62,259 +42,535 @@ fn prep_frame_tmp_dir(tmp_dir: &PathBuf) -> std::io::Result<()> {
     Ok(())
 }
 
-pub fn create_time_based_video(
-    events: Vec<Event>,
-    config: &VidConfig,
-    cam: &CameraParameters,
-) -> std::io::Result<()> {
-    let frame_tmp_dir = if config.keep_frames {
-        config.filename.clone()
-    } else {
-        PathBuf::from(".frames_tmp")
-    };
-    let video_name = Path::new(&config.filename)
-        .file_stem()
-        .unwrap()
-        .to_string_lossy();
+/// Convert an RGB frame into a BGR `Mat` that can be passed to OpenCV
+fn frame_to_mat(img: &FrameImage) -> opencv::Result<Mat> {
+    let rgb = Mat::from_slice(img.as_raw())?;
+    let rgb = rgb.reshape(3, img.height() as i32)?;
 
-    prep_frame_tmp_dir(&frame_tmp_dir)?;
+    let mut bgr = Mat::default();
+    cvt_color(&rgb, &mut bgr, COLOR_RGB2BGR, 0)?;
 
-    let on_color = image::Rgb(colors::GREEN);
-    let off_color = image::Rgb(colors::RED);
-    let black = image::Rgb(colors::BLACK);
+    Ok(bgr)
+}
 
-    const BUF_SIZE: usize = 150;
-    let mut write_buf: Vec<Frame> = Vec::with_capacity(BUF_SIZE);
+/// Draw white text with a black outline so it is readable on any background
+fn draw_outlined_text(
+    frame: &mut Mat,
+    text: &str,
+    origin: Point,
+    font_scale: f64,
+    thickness: i32,
+) -> opencv::Result<()> {
+    for (color, thickness) in [
+        (Scalar::all(0.0), thickness + 2),
+        (Scalar::all(255.0), thickness),
+    ] {
+        imgproc::put_text(
+            frame,
+            text,
+            origin,
+            FONT_HERSHEY_PLAIN,
+            font_scale,
+            color,
+            thickness,
+            LINE_AA,
+            false,
+        )?;
+    }
 
-    // Init canvas
-    let mut img = ImageBuffer::from_fn(u32::from(cam.camera_x), u32::from(cam.camera_y), |_, _| {
-        image::Rgb(colors::BLACK)
-    });
+    Ok(())
+}
 
-    // Define end time relative to the first event
-    let mut end_time: i32 = match events.first() {
-        None => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "No events exist",
-            ))
-        }
-        Some(event) => event.get_timestamp() + config.window_size as i32,
-    };
-    let mut frames_created = 0;
+/// Converts rendered frames into OpenCV frames. This is independent of the
+/// other frames, so frames can be encoded in parallel
+struct FrameEncoder {
+    frame_dir: Option<PathBuf>,
+    encode: bool,
+    save_counts: bool,
+    dimensions: (u8, u8),
+    video_name: String,
+    scale: u32,
+    overlay: bool,
+    scale_bar: Option<u32>,
+}
 
-    for event in events {
-        // Place a pixel on the image canvas with the appropriate color & position
-        place_pixel(config, cam, on_color, off_color, &mut img, &event);
+/// Image and counts of a frame, taken from its renderer before it moves on to the next frame
+struct RenderedFrame {
+    image: Option<FrameImage>,
+    counts: Option<Vec<i32>>,
+    info: FrameInfo,
+}
 
-        if event.get_timestamp() > end_time {
-            frames_created += 1;
-            if frames_created == config.max_frames {
-                break;
+/// A frame that is ready to be written in order
+struct EncodedFrame {
+    frame: Option<Mat>,
+    counts: Option<Vec<i32>>,
+}
+
+impl FrameEncoder {
+    fn new(config: &VidConfig, dimensions: (u8, u8)) -> std::io::Result<Self> {
+        let video_name = Path::new(&config.filename)
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+
+        let frame_dir = if config.keep_frames {
+            prep_frame_dir(&config.filename)?;
+            Some(config.filename.clone())
+        } else {
+            None
+        };
+
+        Ok(FrameEncoder {
+            frame_dir,
+            encode: config.keep_frames || !config.omit_video,
+            save_counts: config.save_counts,
+            dimensions,
+            video_name,
+            scale: config.scale,
+            overlay: config.overlay,
+            scale_bar: config.scale_bar,
+        })
+    }
+
+    /// Draw the frame metadata in the top left and the scale bar in the bottom left corner
+    fn draw_overlay(&self, frame: &mut Mat, index: usize, info: &FrameInfo) -> opencv::Result<()> {
+        let (width, height) = self.dimensions;
+        let width = (u32::from(width) * self.scale) as i32;
+        let height = (u32::from(height) * self.scale) as i32;
+
+        // Scale the text with the frame so it stays legible without covering the events
+        let font_scale = (f64::from(width) / 240.0).clamp(0.6, 3.0);
+        let thickness = font_scale.round().max(1.0) as i32;
+        let line_height = (16.0 * font_scale).round() as i32;
+        let margin = line_height / 2;
+
+        if self.overlay {
+            let rate = match info.event_rate() {
+                Some(rate) => format!("{rate:.0} ev/s"),
+                None => "- ev/s".to_owned(),
+            };
+            let lines = [
+                format!("frame {index}"),
+                format!(
+                    "{:.6}-{:.6} s",
+                    f64::from(info.start_time) / 1e6,
+                    f64::from(info.end_time) / 1e6
+                ),
+                format!("{} events", info.event_count),
+                rate,
+            ];
+
+            for (i, line) in lines.iter().enumerate() {
+                let origin = Point::new(margin, line_height * (i as i32 + 1));
+                draw_outlined_text(frame, line, origin, font_scale, thickness)?;
             }
+        }
+
+        if let Some(length) = self.scale_bar {
+            let bar_start = Point::new(margin, height - margin);
+            let bar_end = Point::new(margin + (length * self.scale) as i32, height - margin);
+            let bar_thickness = (self.scale as i32).max(2);
+
+            imgproc::line(
+                frame,
+                bar_start,
+                bar_end,
+                Scalar::all(0.0),
+                bar_thickness + 2,
+                imgproc::LINE_8,
+                0,
+            )?;
+            imgproc::line(
+                frame,
+                bar_start,
+                bar_end,
+                Scalar::all(255.0),
+                bar_thickness,
+                imgproc::LINE_8,
+                0,
+            )?;
+
+            let label_origin = Point::new(margin, height - margin - bar_thickness - margin / 2);
+            draw_outlined_text(
+                frame,
+                &format!("{length} px"),
+                label_origin,
+                font_scale,
+                thickness,
+            )?;
+        }
 
-            end_time = event.get_timestamp() + config.window_size as i32;
+        Ok(())
+    }
 
-            let count = fs::read_dir(&frame_tmp_dir)?.count() + write_buf.len();
+    /// Take the image and counts of a frame from its renderer
+    fn render(&self, renderer: &FrameRenderer, frame: &Window) -> RenderedFrame {
+        RenderedFrame {
+            image: self.encode.then(|| renderer.render(frame.end_time)),
+            counts: if self.save_counts {
+                renderer.counts().map(<[i32]>::to_vec)
+            } else {
+                None
+            },
+            info: frame.into(),
+        }
+    }
 
-            // Save image to buffer
-            write_buf.push(Frame {
-                img: img.clone(),
-                count,
+    /// Upscale and annotate a rendered frame, saving it as a PNG when frames are kept
+    fn encode(&self, index: usize, rendered: RenderedFrame) -> std::io::Result<EncodedFrame> {
+        let RenderedFrame {
+            image,
+            counts,
+            info,
+        } = rendered;
+
+        let Some(mut img) = image else {
+            return Ok(EncodedFrame {
+                frame: None,
+                counts,
             });
+        };
+
+        // Upscale with nearest neighbour filtering to keep event pixels sharp
+        if self.scale > 1 {
+            img = imageops::resize(
+                &img,
+                img.width() * self.scale,
+                img.height() * self.scale,
+                FilterType::Nearest,
+            );
+        }
 
-            // Write all frames to disk once enough have been saved to buffer
-            if write_buf.len() == BUF_SIZE {
-                for frame in &write_buf {
-                    frame.save_frame(&frame_tmp_dir, &video_name)?;
-                }
-                write_buf.clear();
-            }
+        let mut frame = frame_to_mat(&img).map_err(cv_error)?;
+        if self.overlay || self.scale_bar.is_some() {
+            self.draw_overlay(&mut frame, index, &info)
+                .map_err(cv_error)?;
+        }
 
-            // Reset canvas to black
-            for pixel in img.pixels_mut() {
-                *pixel = black;
+        if let Some(frame_dir) = &self.frame_dir {
+            let path = frame_dir.join(format!("{}_frame{}.png", self.video_name, index));
+            if !imwrite(&path.to_string_lossy(), &frame, &Vector::new()).map_err(cv_error)? {
+                return Err(Error::other("Could not save frame"));
             }
         }
+
+        Ok(EncodedFrame {
+            frame: Some(frame),
+            counts,
+        })
     }
+}
 
-    // Save any remaining frames in buffer
-    for frame in &write_buf {
-        frame.save_frame(&frame_tmp_dir, &video_name)?;
+/// Destination of encoded frames, which must be written in order
+struct FrameSink {
+    video: Option<VideoWriter>,
+    counts: Option<NpzWriter<BufWriter<File>>>,
+    dimensions: (u8, u8),
+    frame_count: usize,
+}
+
+impl FrameSink {
+    fn new(config: &VidConfig, (width, height): (u8, u8)) -> std::io::Result<Self> {
+        let video = if config.omit_video {
+            None
+        } else {
+            // Set video properties
+            let [c1, c2, c3, c4] = config.fourcc;
+            let fourcc = VideoWriter::fourcc(c1, c2, c3, c4).map_err(cv_error)?;
+            let video_filename =
+                format!("{}.{}", config.filename.to_string_lossy(), config.container);
+            let size = Size::new(
+                (u32::from(width) * config.scale) as i32,
+                (u32::from(height) * config.scale) as i32,
+            );
+
+            let video = VideoWriter::new(&video_filename, fourcc, config.fps, size, true)
+                .map_err(cv_error)?;
+
+            // OpenCV only reports unsupported codecs and containers by failing to open the writer
+            if !video.is_opened().map_err(cv_error)? {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    format!(
+                        "Codec {} with container .{} is not supported by the local OpenCV build",
+                        config.fourcc.iter().collect::<String>(),
+                        config.container
+                    ),
+                ));
+            }
+
+            Some(video)
+        };
+
+        let counts = if config.save_counts {
+            let mut counts_filename = config.filename.clone();
+            counts_filename.as_mut_os_string().push("_counts.npz");
+            Some(NpzWriter::new(
+                BufWriter::new(File::create(counts_filename)?),
+                true,
+            ))
+        } else {
+            None
+        };
+
+        Ok(FrameSink {
+            video,
+            counts,
+            dimensions: (width, height),
+            frame_count: 0,
+        })
     }
 
-    // Save any remaining events in current working img
-    let count = std::fs::read_dir(&frame_tmp_dir)?.count();
-    img.save(format!(
-        "{}/{}_tmp_{}.png",
-        frame_tmp_dir.display(),
-        video_name,
-        count
-    ))
-    .unwrap();
-
-    if !config.omit_video {
-        //encode_frames(&video_name, &frame_tmp_dir)?;
-        encode_frames(&config.filename.to_string_lossy(), &frame_tmp_dir, cam)?;
+    fn write_frame(&mut self, encoded: EncodedFrame) -> std::io::Result<()> {
+        if let (Some(npz), Some(counts)) = (&mut self.counts, encoded.counts) {
+            let (width, height) = self.dimensions;
+            npz.add_array(
+                &format!("counts_{}", self.frame_count),
+                &[usize::from(height), usize::from(width)],
+                &counts,
+            )?;
+        }
+
+        if let (Some(video), Some(frame)) = (&mut self.video, encoded.frame) {
+            video.write(&frame).map_err(cv_error)?;
+        }
+
+        self.frame_count += 1;
+        Ok(())
     }
 
-    if !config.keep_frames {
-        prep_frame_tmp_dir(&frame_tmp_dir)?;
+    fn finish(self) -> std::io::Result<()> {
+        // Release the VideoWriter
+        if let Some(mut video) = self.video {
+            video.release().map_err(cv_error)?;
+        }
+
+        if let Some(npz) = self.counts {
+            npz.finish()?.flush()?;
+        }
+
+        Ok(())
     }
+}
 
-    Ok(())
+/// Run `op` in `pool`, or in the current thread pool without one
+fn install<R: Send>(pool: Option<&ThreadPool>, op: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
 }
 
-pub fn create_event_based_video(
-    events: Vec<Event>,
+/// Render the frames and write them to the video in order. Frames are encoded in parallel
+/// batches, and rendered in parallel when they only depend on their own events
+fn render_video(
+    events: &[Event],
+    frames: &[Window],
     config: &VidConfig,
     cam: &CameraParameters,
 ) -> std::io::Result<()> {
-    let frame_tmp_dir = if config.keep_frames {
-        config.filename.clone()
-    } else {
-        PathBuf::from(".frames_tmp")
+    let undistortion = match &config.calibration {
+        Some(path) => Some(Undistortion::from_calibration_file(path, cam)?),
+        None => None,
     };
-    let video_name = Path::new(&config.filename)
-        .file_stem()
-        .unwrap()
-        .to_string_lossy();
-
-    prep_frame_tmp_dir(&frame_tmp_dir)?;
 
-    let on_color = image::Rgb(colors::GREEN);
-    let off_color = image::Rgb(colors::RED);
-    let black = image::Rgb(colors::BLACK);
+    let dimensions = config.transform.dimensions(cam);
+    let encoder = FrameEncoder::new(config, dimensions)?;
+    let mut sink = FrameSink::new(config, dimensions)?;
+
+    // Without --threads, frames are processed in the current thread pool. In a batch
+    // conversion that is the pool of --jobs workers, which the files then share instead of
+    // each starting one thread per core
+    let pool = match config.threads {
+        0 => None,
+        threads => Some(
+            ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(Error::other)?,
+        ),
+    };
 
-    const BUF_SIZE: usize = 150;
-    let mut write_buf: Vec<Frame> = Vec::with_capacity(BUF_SIZE);
+    // Decaying canvases and time surfaces depend on every earlier event, so their frames are
+    // rendered in order by a single renderer that carries its state over between frames
+    let mut sequential_renderer = match config.render_mode {
+        RenderMode::Decay { .. } | RenderMode::TimeSurface { .. } => {
+            Some(FrameRenderer::new(config, cam, undistortion.as_ref()))
+        }
+        RenderMode::LastEvent | RenderMode::Counts { .. } => None,
+    };
+    let mut rendered_events = 0;
+
+    // Limit the number of frames held in memory while they wait to be written
+    let batch_size = install(pool.as_ref(), rayon::current_num_threads) * 4;
+
+    for (batch_index, batch) in frames.chunks(batch_size).enumerate() {
+        let rendered: Vec<RenderedFrame> = match &mut sequential_renderer {
+            Some(renderer) => batch
+                .iter()
+                .map(|frame| {
+                    // Events between frames still update the state
+                    let end = frame.events.end.max(rendered_events);
+                    for event in &events[rendered_events..end] {
+                        renderer.add_event(event);
+                    }
+                    rendered_events = end;
+
+                    let rendered = encoder.render(renderer, frame);
+                    renderer.next_frame();
+                    rendered
+                })
+                .collect(),
+            None => install(pool.as_ref(), || {
+                batch
+                    .par_iter()
+                    .map(|frame| {
+                        let mut renderer = FrameRenderer::new(config, cam, undistortion.as_ref());
+                        for event in &events[frame.events.clone()] {
+                            renderer.add_event(event);
+                        }
+                        encoder.render(&renderer, frame)
+                    })
+                    .collect()
+            }),
+        };
+
+        let encoded = install(pool.as_ref(), || {
+            rendered
+                .into_par_iter()
+                .enumerate()
+                .map(|(i, frame)| encoder.encode(batch_index * batch_size + i, frame))
+                .collect::<std::io::Result<Vec<_>>>()
+        })?;
+
+        for frame in encoded {
+            sink.write_frame(frame)?;
+        }
+    }
 
-    // Init canvas
-    let mut img = ImageBuffer::from_fn(u32::from(cam.camera_x), u32::from(cam.camera_y), |_, _| {
-        image::Rgb(colors::BLACK)
-    });
+    sink.finish()
+}
 
-    let mut events_in_current_frame = 0;
-    let max_events = config.window_size;
-    let mut frames_created = 0;
+/// Frames of a time based video, limited to `max_frames`. Without a stride, a frame ends
+/// with the first event after its end time and the next frame starts at that event.
+/// With a stride, frames are overlapping sliding windows
+#[must_use]
+pub fn time_based_frames(
+    events: &[Event],
+    window_size: usize,
+    stride: Option<usize>,
+    max_frames: usize,
+) -> Vec<Window> {
+    // Overlapping frames are found in a single pass over the events
+    if let Some(stride) = stride {
+        return sliding_windows(events, window_size, stride, max_frames);
+    }
 
-    for event in events {
-        // Place a pixel on the image canvas with the appropriate color & position
-        place_pixel(config, cam, on_color, off_color, &mut img, &event);
-        events_in_current_frame += 1;
+    // Define end time relative to the first event
+    let Some(first) = events.first() else {
+        return Vec::new();
+    };
+    let mut end_time = first.get_timestamp() + window_size as i32;
 
-        if events_in_current_frame == max_events {
-            frames_created += 1;
-            events_in_current_frame = 0;
+    // Find the events of each frame. The event that ends a window is part of its frame
+    let mut frames = Vec::new();
+    let mut start = 0;
 
-            if frames_created == config.max_frames {
+    for (i, event) in events.iter().enumerate() {
+        if event.get_timestamp() > end_time {
+            frames.push(Window {
+                events: start..i + 1,
+                start_time: end_time - window_size as i32,
+                end_time,
+            });
+            start = i + 1;
+            if frames.len() == max_frames {
                 break;
             }
 
-            let count = fs::read_dir(&frame_tmp_dir)?.count() + write_buf.len();
+            end_time = event.get_timestamp() + window_size as i32;
+        }
+    }
 
-            // Save image to buffer
-            write_buf.push(Frame {
-                img: img.clone(),
-                count,
-            });
+    // Any remaining events form the last frame
+    if frames.len() != max_frames {
+        frames.push(Window {
+            events: start..events.len(),
+            start_time: end_time - window_size as i32,
+            end_time,
+        });
+    }
 
-            // Write all frames to disk once enough have been saved to buffer
-            if write_buf.len() == BUF_SIZE {
-                for frame in &write_buf {
-                    frame.save_frame(&frame_tmp_dir, &video_name)?;
-                }
-                write_buf.clear();
-            }
+    frames
+}
 
-            // Reset canvas to black
-            for pixel in img.pixels_mut() {
-                *pixel = black;
-            }
-        }
-    }
+/// Window spanning from the first to the last of the given events. Windows without events
+/// are placed at the last event
+fn event_window(events: &[Event], range: Range<usize>) -> Window {
+    let last = events.len().saturating_sub(1);
+    let timestamp = |i: usize| events.get(i.min(last)).map_or(0, Event::get_timestamp);
 
-    // Save any remaining frames in buffer
-    for frame in &write_buf {
-        frame.save_frame(&frame_tmp_dir, &video_name)?;
+    Window {
+        start_time: timestamp(range.start),
+        end_time: timestamp(range.end.saturating_sub(1).max(range.start)),
+        events: range,
     }
+}
 
-    // Save any remaining events in current working img
-    let count = std::fs::read_dir(&frame_tmp_dir)?.count();
-    img.save(format!(
-        "{}/{}_tmp_{}.png",
-        frame_tmp_dir.display(),
-        video_name,
-        count
-    ))
-    .unwrap();
-
-    if !config.omit_video {
-        encode_frames(&config.filename.to_string_lossy(), &frame_tmp_dir, cam)?;
+/// Frames of `window_size` events each, limited to `max_frames`. Any remaining events form
+/// the last frame
+#[must_use]
+pub fn event_based_frames(events: &[Event], window_size: usize, max_frames: usize) -> Vec<Window> {
+    let mut frames = Vec::new();
+    let mut start = 0;
+
+    for i in 0..events.len() {
+        if i + 1 - start == window_size {
+            frames.push(event_window(events, start..i + 1));
+            start = i + 1;
+            if frames.len() == max_frames {
+                break;
+            }
+        }
     }
 
-    if !config.keep_frames {
-        prep_frame_tmp_dir(&frame_tmp_dir)?;
+    if frames.len() != max_frames {
+        frames.push(event_window(events, start..events.len()));
     }
 
-    Ok(())
+    frames
 }
 
-fn place_pixel(
+pub fn create_time_based_video(
+    events: Vec<Event>,
     config: &VidConfig,
     cam: &CameraParameters,
-    on_color: image::Rgb<u8>,
-    off_color: image::Rgb<u8>,
-    img: &mut ImageBuffer<image::Rgb<u8>, Vec<u8>>,
-    event: &Event,
-) {
-    let (x, y) = event.get_coords(&cam.camera_type);
-
-    let event_polarity = event.get_polarity(&cam.camera_type);
-
-    if !config.exclude_on && event_polarity {
-        img.put_pixel(u32::from(x - 1), u32::from(y - 1), on_color);
-    } else if !config.exclude_off && !event_polarity {
-        img.put_pixel(u32::from(x - 1), u32::from(y - 1), off_color);
+) -> std::io::Result<()> {
+    let frames = time_based_frames(
+        &events,
+        config.window_size,
+        config.stride,
+        config.max_frames,
+    );
+
+    if frames.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "No events exist",
+        ));
     }
+
+    render_video(&events, &frames, config, cam)
 }
 
-fn encode_frames(
-    filename: &str,
-    frame_tmp_dir: &PathBuf,
+pub fn create_event_based_video(
+    events: Vec<Event>,
+    config: &VidConfig,
     cam: &CameraParameters,
 ) -> std::io::Result<()> {
-    let paths = fs::read_dir(frame_tmp_dir).expect("Could not open frame tmp directory");
-    let mut image_files: Vec<String> = paths
-        .filter_map(|entry| entry.ok())
-        .map(|entry| entry.path().display().to_string())
-        .collect();
-
-    image_files.sort_by(|a, b| natord::compare(a, b));
-
-    // Set video properties
-    let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
-    let fps = 30.0;
-    let video_filename = filename.to_owned() + ".avi";
-
-    // Create a VideoWriter
-    let mut video = VideoWriter::new(
-        &video_filename,
-        fourcc,
-        fps,
-        Size::new(i32::from(cam.camera_x), i32::from(cam.camera_y)),
-        true,
-    )
-    .expect("Could not initialize video writer");
-
-    // Write each image frame to the video
-    for image_file in image_files {
-        let frame = imread(&image_file, IMREAD_COLOR).unwrap();
-        video.write(&frame).expect("Could not encode file to video");
-    }
-
-    // Release the VideoWriter
-    video.release().expect("Could not finalize video");
+    let frames = event_based_frames(&events, config.window_size, config.max_frames);
 
-    Ok(())
+    render_video(&events, &frames, config, cam)
 }
@@ -0,0 +1,133 @@
+use std::ops::Range;
+
+use crate::{aedat_data::Event, cli_configs::WindowMode};
+
+/// A slice of the event stream, e.g. a tensor, a video frame or a CSV row
+pub struct Window {
+    pub events: Range<usize>,
+    pub start_time: i32,
+    pub end_time: i32,
+}
+
+/// Split events into windows of a fixed duration or a fixed number of events.
+///
+/// Time based windows are back-to-back and include windows without any events,
+/// so that every window covers exactly `window_size` microseconds.
+/// Event based windows span from their first to their last event
+#[must_use]
+pub fn split_windows(events: &[Event], mode: &WindowMode, window_size: usize) -> Vec<Window> {
+    let mut windows = Vec::new();
+
+    match mode {
+        WindowMode::Time => {
+            let Some(first) = events.first() else {
+                return windows;
+            };
+
+            let mut start_time = first.get_timestamp();
+            let mut start = 0;
+
+            for (i, event) in events.iter().enumerate() {
+                while event.get_timestamp() >= start_time + window_size as i32 {
+                    windows.push(Window {
+                        events: start..i,
+                        start_time,
+                        end_time: start_time + window_size as i32,
+                    });
+                    start = i;
+                    start_time += window_size as i32;
+                }
+            }
+
+            if start < events.len() {
+                windows.push(Window {
+                    events: start..events.len(),
+                    start_time,
+                    end_time: start_time + window_size as i32,
+                });
+            }
+        }
+        WindowMode::EventCount => {
+            for start in (0..events.len()).step_by(window_size) {
+                let end = (start + window_size).min(events.len());
+
+                windows.push(Window {
+                    events: start..end,
+                    start_time: events[start].get_timestamp(),
+                    end_time: events[end - 1].get_timestamp(),
+                });
+            }
+        }
+    }
+
+    windows
+}
+
+/// Up to `max_windows` overlapping windows of `window_size` microseconds that start every
+/// `stride` microseconds, beginning at the first event. Windows without events are included.
+///
+/// The event ranges are found in a single pass, so every event is visited
+/// a constant number of times regardless of how much the windows overlap
+#[must_use]
+pub fn sliding_windows(
+    events: &[Event],
+    window_size: usize,
+    stride: usize,
+    max_windows: usize,
+) -> Vec<Window> {
+    let mut windows = Vec::new();
+
+    let (Some(first), Some(last)) = (events.first(), events.last()) else {
+        return windows;
+    };
+
+    let mut start_time = i64::from(first.get_timestamp());
+    let last_time = i64::from(last.get_timestamp());
+    let (mut start, mut end) = (0, 0);
+
+    while start_time <= last_time && windows.len() < max_windows {
+        let end_time = start_time + window_size as i64;
+
+        while start < events.len() && i64::from(events[start].get_timestamp()) < start_time {
+            start += 1;
+        }
+        end = end.max(start);
+        while end < events.len() && i64::from(events[end].get_timestamp()) < end_time {
+            end += 1;
+        }
+
+        windows.push(Window {
+            events: start..end,
+            start_time: start_time as i32,
+            end_time: end_time as i32,
+        });
+        start_time += stride as i64;
+    }
+
+    windows
+}
+
+/// Index of the first event at or after each millisecond since the first event
+#[cfg_attr(not(feature = "hdf5"), allow(dead_code))]
+#[must_use]
+pub fn millisecond_index(events: &[Event]) -> Vec<u64> {
+    let (Some(first), Some(last)) = (events.first(), events.last()) else {
+        return Vec::new();
+    };
+
+    let first_time = i64::from(first.get_timestamp());
+    let milliseconds = (i64::from(last.get_timestamp()) - first_time).max(0) / 1000 + 1;
+
+    let mut index = Vec::with_capacity(milliseconds as usize);
+    let mut i = 0;
+
+    for ms in 0..milliseconds {
+        let start_time = first_time + (ms * 1000);
+        while i < events.len() && i64::from(events[i].get_timestamp()) < start_time {
+            i += 1;
+        }
+        index.push(i as u64);
+    }
+
+    index
+}
@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum CameraType {
     DVS128,
     DAVIS240,
@@ -33,6 +33,26 @@ pub struct Event {
 }
 
 impl Event {
+    /// Encode an event with 1-based coordinates, the inverse of `get_coords`, `get_polarity`
+    /// and `get_timestamp`
+    #[must_use]
+    pub fn new(x: u8, y: u8, polarity: bool, timestamp: i32, cam_type: &CameraType) -> Event {
+        let t = timestamp.to_be_bytes();
+        let p = u8::from(polarity);
+
+        let (b0, b1, b2, b3) = match cam_type {
+            CameraType::DVS128 => (0, 0, 128 - y, ((128 - x) << 1) | p),
+            CameraType::DAVIS240 => {
+                let (x, y) = (240 - x, 180 - y);
+                (y >> 2, (y << 6) | (x >> 4), (x << 4) | (p << 3), 0)
+            }
+        };
+
+        Event {
+            bytes: [b0, b1, b2, b3, t[0], t[1], t[2], t[3]],
+        }
+    }
+
     #[must_use]
     pub fn get_polarity(&self, cam_type: &CameraType) -> bool {
         match cam_type {
@@ -83,17 +103,14 @@ pub fn get_events(
     const EVENT_SIZE: usize = 8;
 
     // Skip over the header to get directly to the event data
-    let aedat_iter = aedat_file
-        .iter()
-        .skip(end_of_header_index as usize)
-        .enumerate();
+    let aedat_iter = aedat_file.iter().skip(end_of_header_index as usize);
 
     // Pre-allocate space in vec for all events
     let mut events = Vec::with_capacity(aedat_iter.len() / EVENT_SIZE);
     let mut bytes_tmp = Vec::with_capacity(EVENT_SIZE);
 
     // Loop over all bytes in file
-    for (_i, &item) in aedat_iter {
+    for &item in aedat_iter {
         bytes_tmp.push(item.to_owned());
 
         // Collected enough bytes for an event. Create event and push to Vec of events
@@ -109,3 +126,17 @@ pub fn get_events(
     }
     Ok(events)
 }
+
+/// Write an AEDAT 2.0 file from its ASCII header and events
+pub fn write_aedat<W: std::io::Write>(
+    writer: &mut W,
+    events: &[Event],
+    header: &str,
+) -> Result<(), std::io::Error> {
+    writer.write_all(header.as_bytes())?;
+
+    for event in events {
+        writer.write_all(&event.bytes)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,283 @@
+//! Conversion of several input files, given as files, directories or glob patterns
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    panic::{self, AssertUnwindSafe},
+    path::{Component, Path, PathBuf},
+};
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use crate::cli_configs::FilePaths;
+use crate::compression::{is_stdio, strip_compression_extension};
+
+/// Input file of a batch conversion
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchInput {
+    pub path: PathBuf,
+    /// Path of the file relative to the parent of the directory it was found in, or to the
+    /// directory before the first wildcard of its glob, which is mirrored in the output directory
+    pub relative: PathBuf,
+}
+
+/// Outcome of converting one file of a batch
+pub struct BatchResult {
+    pub input: PathBuf,
+    pub result: Result<(), String>,
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Returns true if the filenames cannot be converted as a single file
+#[must_use]
+pub fn is_batch(filenames: &[PathBuf]) -> bool {
+    match filenames {
+        [filename] => is_glob(filename) || filename.is_dir(),
+        _ => true,
+    }
+}
+
+/// Files found in directories are converted if they hold events, judged by their extension
+fn is_event_file(path: &Path, include_csv: bool) -> bool {
+    let path = strip_compression_extension(path);
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("aedat" | "aedc") => true,
+        Some("csv") => include_csv,
+        _ => false,
+    }
+}
+
+/// Add the event files in a directory and its subdirectories
+fn walk_dir(
+    dir: &Path,
+    root: &Path,
+    include_csv: bool,
+    inputs: &mut Vec<BatchInput>,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            walk_dir(&path, root, include_csv, inputs)?;
+        } else if is_event_file(&path, include_csv) {
+            add_file(path, root, inputs);
+        }
+    }
+
+    Ok(())
+}
+
+fn add_file(path: PathBuf, root: &Path, inputs: &mut Vec<BatchInput>) {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => PathBuf::from(path.file_name().unwrap_or_default()),
+    };
+
+    inputs.push(BatchInput { path, relative });
+}
+
+/// Directory holding every match of a glob pattern, i.e. the components before the first one
+/// with a wildcard
+fn glob_root(pattern: &Path) -> PathBuf {
+    pattern
+        .components()
+        .take_while(|component| !is_glob(Path::new(component.as_os_str())))
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Expand files, directories (recursively) and glob patterns into the files to convert. Files
+/// in directories are only converted if they have an AEDAT, compact event or, when
+/// `include_csv` is set, CSV extension
+pub fn collect_inputs(filenames: &[PathBuf], include_csv: bool) -> io::Result<Vec<BatchInput>> {
+    let mut inputs = Vec::new();
+
+    for filename in filenames {
+        if is_stdio(filename) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "Standard input cannot be read when converting several files",
+            ));
+        }
+
+        if is_glob(filename) {
+            let root = glob_root(filename);
+            let matches = glob::glob(&filename.to_string_lossy())
+                .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err.to_string()))?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| io::Error::other(err.to_string()))?;
+
+            if matches.is_empty() {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("No files match {}", filename.display()),
+                ));
+            }
+
+            for path in matches {
+                if path.is_dir() {
+                    walk_dir(&path, &root, include_csv, &mut inputs)?;
+                } else {
+                    add_file(path, &root, &mut inputs);
+                }
+            }
+        } else if filename.is_dir() {
+            // The directory itself is mirrored, so that directories holding files of the same
+            // name are kept apart
+            let root = match filename.file_name() {
+                Some(_) => filename.parent().unwrap_or(Path::new("")),
+                None => filename,
+            };
+            walk_dir(filename, root, include_csv, &mut inputs)?;
+        } else {
+            // Files that were named explicitly are converted whatever their extension
+            let root = filename.parent().unwrap_or(Path::new(""));
+            add_file(filename.clone(), root, &mut inputs);
+        }
+    }
+
+    inputs.sort_by(|a, b| a.path.cmp(&b.path));
+    inputs.dedup_by(|a, b| a.path == b.path);
+
+    Ok(inputs)
+}
+
+impl BatchInput {
+    /// Paths of the conversion of this file. Outputs are written next to the input file, or
+    /// into the same relative location under `output_root`
+    #[must_use]
+    pub fn file_paths(&self, output_root: Option<&Path>) -> FilePaths {
+        let output_dir = output_root.map(|root| match self.relative.parent() {
+            Some(parent) => root.join(parent),
+            None => root.to_path_buf(),
+        });
+
+        FilePaths {
+            input: self.path.clone(),
+            output: None,
+            output_dir,
+        }
+    }
+}
+
+/// Refuse to convert inputs whose outputs would overwrite each other. Outputs are named after
+/// their input without its extensions, e.g. explicitly named files with the same name in
+/// different directories, or `rec.aedat` next to `rec.aedc`
+fn check_outputs(inputs: &[BatchInput], output_root: Option<&Path>) -> io::Result<()> {
+    let mut outputs = HashMap::new();
+
+    for input in inputs {
+        let paths = input.file_paths(output_root);
+        let stem = strip_compression_extension(&input.path).with_extension("");
+        let output = match (&paths.output_dir, stem.file_name()) {
+            (Some(dir), Some(name)) => dir.join(name),
+            _ => stem,
+        };
+
+        if let Some(other) = outputs.insert(output, &input.path) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} and {} would be converted to the same output",
+                    other.display(),
+                    input.path.display()
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(msg), _) => (*msg).to_string(),
+        (_, Some(msg)) => msg.clone(),
+        _ => "Conversion panicked".to_string(),
+    }
+}
+
+/// Convert every input on up to `jobs` threads (one per core if 0). A file that fails to
+/// convert is recorded in its result instead of stopping the other conversions. Nothing is
+/// converted if two inputs would have the same output
+pub fn run_batch<F>(
+    inputs: &[BatchInput],
+    output_root: Option<&Path>,
+    jobs: usize,
+    convert: F,
+) -> io::Result<Vec<BatchResult>>
+where
+    F: Fn(&FilePaths) -> io::Result<()> + Sync,
+{
+    if output_root.is_some_and(is_stdio) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "Cannot write to standard output when converting several files",
+        ));
+    }
+
+    check_outputs(inputs, output_root)?;
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .map_err(io::Error::other)?;
+
+    let results = pool.install(|| {
+        inputs
+            .par_iter()
+            .map(|input| {
+                let paths = input.file_paths(output_root);
+
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    if let Some(dir) = &paths.output_dir {
+                        fs::create_dir_all(dir)?;
+                    }
+                    convert(&paths)
+                }));
+
+                BatchResult {
+                    input: input.path.clone(),
+                    result: match result {
+                        Ok(Ok(())) => Ok(()),
+                        Ok(Err(err)) => Err(err.to_string()),
+                        Err(payload) => Err(panic_message(payload.as_ref())),
+                    },
+                }
+            })
+            .collect()
+    });
+
+    Ok(results)
+}
+
+/// Print the outcome of each file and the number of files that succeeded and failed. Returns
+/// the number of failures
+pub fn print_summary(results: &[BatchResult]) -> usize {
+    let mut failed = 0;
+
+    eprintln!();
+    for BatchResult { input, result } in results {
+        match result {
+            Ok(()) => eprintln!("OK      {}", input.display()),
+            Err(err) => {
+                failed += 1;
+                eprintln!("FAILED  {}: {err}", input.display());
+            }
+        }
+    }
+    eprintln!("{} succeeded, {failed} failed", results.len() - failed);
+
+    failed
+}
@@ -1,5 +1,164 @@
 use clap::ArgMatches;
-use std::path::PathBuf;
+use std::{ops::Range, path::PathBuf};
+
+use crate::aedat_conversions::rendering::{parse_hex_color, Colormap, CountNormalization, Palette};
+use crate::aedat_data::CameraType;
+use crate::compact::CompactOptions;
+use crate::compression::{is_stdio, strip_compression_extension, OutputCompression, STDIO};
+use crate::transform::{Rotation, Transform};
+
+/// Input file of a conversion and where its output goes
+pub struct FilePaths {
+    pub input: PathBuf,
+    /// Output path given with --output
+    pub output: Option<PathBuf>,
+    /// Directory that derived output filenames are placed in, used by batch conversions
+    pub output_dir: Option<PathBuf>,
+}
+
+impl FilePaths {
+    /// Paths of a conversion of a single file
+    pub fn new(args: &ArgMatches) -> FilePaths {
+        FilePaths {
+            input: args.get_one::<PathBuf>("filename").unwrap().clone(),
+            output: args.get_one::<PathBuf>("output").cloned(),
+            output_dir: None,
+        }
+    }
+}
+
+/// Input filename without any compression extension, from which output filenames are derived
+fn input_filename(paths: &FilePaths) -> PathBuf {
+    strip_compression_extension(&paths.input)
+}
+
+/// Refuse to write an output file over the input file
+fn check_overwrite(paths: &FilePaths, output: &PathBuf) -> Result<(), std::io::Error> {
+    if !is_stdio(output) && *output == paths.input {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "The output file {} would overwrite the input file",
+                output.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Output path given with --output, or derived from the input filename. Only text outputs can
+/// be written to standard output, which is the default when reading from standard input
+pub fn output_filename(
+    paths: &FilePaths,
+    text: bool,
+    derive: impl FnOnce(PathBuf) -> PathBuf,
+) -> Result<PathBuf, std::io::Error> {
+    let output = match &paths.output {
+        Some(output) => output.clone(),
+        None if is_stdio(&paths.input) && text => PathBuf::from(STDIO),
+        None if is_stdio(&paths.input) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--output is required when reading from standard input",
+            ))
+        }
+        None => {
+            let derived = derive(input_filename(paths));
+            match (&paths.output_dir, derived.file_name()) {
+                (Some(dir), Some(name)) => dir.join(name),
+                _ => derived,
+            }
+        }
+    };
+
+    if is_stdio(&output) && !text {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Only CSV output can be written to standard output",
+        ));
+    }
+
+    check_overwrite(paths, &output)?;
+    Ok(output)
+}
+
+/// Output path and compression of a CSV. --compress appends its extension to the filename,
+/// otherwise the compression follows the extension of the filename
+pub fn text_output(
+    args: &ArgMatches,
+    paths: &FilePaths,
+    derive: impl FnOnce(PathBuf) -> PathBuf,
+) -> Result<(PathBuf, OutputCompression), std::io::Error> {
+    let filename = output_filename(paths, true, derive)?;
+
+    let (filename, compression) = match parse_compression(args) {
+        OutputCompression::None => {
+            let compression = OutputCompression::from_path(&filename);
+            (filename, compression)
+        }
+        compression if is_stdio(&filename) => (filename, compression),
+        compression => (compression.apply_extension(filename), compression),
+    };
+
+    check_overwrite(paths, &filename)?;
+    Ok((filename, compression))
+}
+
+fn parse_compression(args: &ArgMatches) -> OutputCompression {
+    match args.get_one::<String>("compress").map(String::as_str) {
+        Some("gzip") => OutputCompression::Gzip,
+        Some("zstd") => OutputCompression::Zstd,
+        _ => OutputCompression::None,
+    }
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<char, std::io::Error> {
+    match delimiter {
+        "tab" | "\\t" => Ok('\t'),
+        "space" => Ok(' '),
+        delimiter => {
+            let mut chars = delimiter.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c != '\n' && c != '"' => Ok(c),
+                _ => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "The delimiter must be a single character, tab or space",
+                )),
+            }
+        }
+    }
+}
+
+fn parse_time_unit(args: &ArgMatches, id: &str) -> TimeUnit {
+    match args.get_one::<String>(id).map(String::as_str) {
+        Some("ms") => TimeUnit::Milliseconds,
+        Some("s") => TimeUnit::Seconds,
+        _ => TimeUnit::Microseconds,
+    }
+}
+
+fn parse_polarity_encoding(args: &ArgMatches, id: &str) -> PolarityEncoding {
+    match args.get_one::<String>(id).map(String::as_str) {
+        Some("binary") => PolarityEncoding::Binary,
+        _ => PolarityEncoding::Signed,
+    }
+}
+
+fn parse_transform(args: &ArgMatches) -> Transform {
+    let rotation = match args.get_one::<String>("rotate").map(String::as_str) {
+        Some("90") => Rotation::Rotate90,
+        Some("180") => Rotation::Rotate180,
+        Some("270") => Rotation::Rotate270,
+        _ => Rotation::None,
+    };
+
+    Transform {
+        flip_x: args.get_flag("flipX"),
+        flip_y: args.get_flag("flipY"),
+        transpose: args.get_flag("transpose"),
+        rotation,
+    }
+}
 
 pub enum CoordMode {
     NoCoord,
@@ -7,17 +166,174 @@ pub enum CoordMode {
     PixelNum,
 }
 
+/// Order in which pixels are numbered
+pub enum PixelOrder {
+    RowMajor,
+    ColumnMajor,
+}
+
+/// Convention used for the `Pixel Number` column
+pub struct PixelNumbering {
+    pub order: PixelOrder,
+    /// Number of the first pixel, 0 or 1
+    pub base: u32,
+}
+
+impl Default for PixelNumbering {
+    fn default() -> Self {
+        PixelNumbering {
+            order: PixelOrder::RowMajor,
+            base: 0,
+        }
+    }
+}
+
+impl PixelNumbering {
+    /// Number of the pixel at 1-based coordinates on a sensor of the given size
+    #[must_use]
+    pub fn pixel_number(&self, (x, y): (u8, u8), (width, height): (u8, u8)) -> u32 {
+        let (x, y) = (u32::from(x - 1), u32::from(y - 1));
+        let index = match self.order {
+            PixelOrder::RowMajor => u32::from(width) * y + x,
+            PixelOrder::ColumnMajor => u32::from(height) * x + y,
+        };
+        index + self.base
+    }
+}
+
+/// A column of the event CSV
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventColumn {
+    Polarity,
+    X,
+    Y,
+    PixelNumber,
+    XUndist,
+    YUndist,
+    Timestamp,
+}
+
+impl EventColumn {
+    /// Column selected by its `--column_order` name
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "p" => Some(EventColumn::Polarity),
+            "x" => Some(EventColumn::X),
+            "y" => Some(EventColumn::Y),
+            "pixel_number" => Some(EventColumn::PixelNumber),
+            "x_undist" => Some(EventColumn::XUndist),
+            "y_undist" => Some(EventColumn::YUndist),
+            "t" => Some(EventColumn::Timestamp),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn header(&self) -> &'static str {
+        match self {
+            EventColumn::Polarity => "On/Off",
+            EventColumn::X => "X",
+            EventColumn::Y => "Y",
+            EventColumn::PixelNumber => "Pixel Number",
+            EventColumn::XUndist => "X_undist",
+            EventColumn::YUndist => "Y_undist",
+            EventColumn::Timestamp => "Timestamp",
+        }
+    }
+}
+
+/// Unit of the exported timestamps
+pub enum TimeUnit {
+    Microseconds,
+    Milliseconds,
+    Seconds,
+}
+
+/// Values written for ON and OFF events
+pub enum PolarityEncoding {
+    /// 1 and -1
+    Signed,
+    /// 1 and 0
+    Binary,
+}
+
+/// Formatting of the CSV export. These options do not apply to the other event formats
+pub struct CsvFormatting {
+    pub delimiter: char,
+    /// Columns that are moved to the front, in this order. Other columns keep their default order
+    pub column_order: Vec<EventColumn>,
+    pub time_unit: TimeUnit,
+    pub polarity: PolarityEncoding,
+    pub header: bool,
+}
+
+impl Default for CsvFormatting {
+    fn default() -> Self {
+        CsvFormatting {
+            delimiter: ',',
+            column_order: Vec::new(),
+            time_unit: TimeUnit::Microseconds,
+            polarity: PolarityEncoding::Signed,
+            header: true,
+        }
+    }
+}
+
+/// File format of the event export
+pub enum EventFormat {
+    Csv,
+    Parquet,
+    ArrowIpc,
+    Npy,
+    Npz,
+}
+
 pub struct CsvConfig {
     pub filename: PathBuf,
+    pub compression: OutputCompression,
+    pub format: EventFormat,
     pub include_polarity: bool,
     pub coords: CoordMode,
     pub offset_time: bool,
+    pub transform: Transform,
+    pub calibration: Option<PathBuf>,
+    pub round_undistorted: bool,
+    pub pixel_numbering: PixelNumbering,
+    pub formatting: CsvFormatting,
 }
 
 impl CsvConfig {
-    pub fn new(args: &ArgMatches) -> Result<CsvConfig, std::io::Error> {
-        let mut filename = args.get_one::<PathBuf>("filename").unwrap().clone();
-        filename.set_extension("csv");
+    pub fn new(args: &ArgMatches, paths: &FilePaths) -> Result<CsvConfig, std::io::Error> {
+        let format = match args.get_one::<String>("format").map(String::as_str) {
+            Some("parquet") => EventFormat::Parquet,
+            Some("arrow") => EventFormat::ArrowIpc,
+            Some("npy") => EventFormat::Npy,
+            Some("npz") => EventFormat::Npz,
+            _ => EventFormat::Csv,
+        };
+
+        let extension = match format {
+            EventFormat::Csv => "csv",
+            EventFormat::Parquet => "parquet",
+            EventFormat::ArrowIpc => "arrow",
+            EventFormat::Npy => "npy",
+            EventFormat::Npz => "npz",
+        };
+
+        let (filename, compression) = match format {
+            EventFormat::Csv => text_output(args, paths, |f| f.with_extension(extension))?,
+            _ if parse_compression(args) != OutputCompression::None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "--compress only applies to CSV output",
+                ))
+            }
+            _ => (
+                output_filename(paths, false, |f| f.with_extension(extension))?,
+                OutputCompression::None,
+            ),
+        };
 
         let include_polarity = args.get_flag("includePolarity");
         let exclude_polarity = args.get_flag("excludePolarity");
@@ -40,39 +356,245 @@ impl CsvConfig {
         };
 
         let offset_time = args.get_flag("offsetTime");
+        let transform = parse_transform(args);
+
+        let calibration = args.get_one::<PathBuf>("calibration").cloned();
+        let round_undistorted = args.get_flag("roundUndistorted");
+
+        let pixel_numbering = PixelNumbering {
+            order: match args.get_one::<String>("pixelOrder").map(String::as_str) {
+                Some("column_major") => PixelOrder::ColumnMajor,
+                _ => PixelOrder::RowMajor,
+            },
+            base: args.get_one::<u32>("pixelBase").unwrap().to_owned(),
+        };
+
+        let delimiter = parse_delimiter(args.get_one::<String>("delimiter").unwrap())?;
+
+        let column_order: Vec<EventColumn> = args
+            .get_many::<String>("columnOrder")
+            .unwrap_or_default()
+            .filter_map(|name| EventColumn::from_name(name))
+            .collect();
+
+        for (i, column) in column_order.iter().enumerate() {
+            if column_order[..i].contains(column) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Column '{}' is listed more than once", column.header()),
+                ));
+            }
+        }
 
-        Ok(CsvConfig {
+        let time_unit = parse_time_unit(args, "timeUnit");
+        let polarity = parse_polarity_encoding(args, "polarityEncoding");
+
+        let config = CsvConfig {
             filename,
+            compression,
+            format,
             include_polarity,
             coords,
             offset_time,
-        })
+            transform,
+            calibration,
+            round_undistorted,
+            pixel_numbering,
+            formatting: CsvFormatting {
+                delimiter,
+                column_order,
+                time_unit,
+                polarity,
+                header: !args.get_flag("noHeader"),
+            },
+        };
+
+        // Ordered columns must be part of the export
+        let columns = config.csv_columns();
+        if let Some(column) = config
+            .formatting
+            .column_order
+            .iter()
+            .find(|column| !columns.contains(column))
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Column '{}' is not part of the export", column.header()),
+            ));
+        }
+
+        Ok(config)
+    }
+
+    /// Columns of the CSV export, in the order they are written
+    #[must_use]
+    pub fn csv_columns(&self) -> Vec<EventColumn> {
+        let mut columns = Vec::new();
+
+        if self.include_polarity {
+            columns.push(EventColumn::Polarity);
+        }
+
+        match self.coords {
+            CoordMode::NoCoord => (),
+            CoordMode::XY => columns.extend([EventColumn::X, EventColumn::Y]),
+            CoordMode::PixelNum => columns.push(EventColumn::PixelNumber),
+        }
+
+        if self.calibration.is_some() {
+            columns.extend([EventColumn::XUndist, EventColumn::YUndist]);
+        }
+
+        columns.push(EventColumn::Timestamp);
+
+        // Listed columns come first, followed by the rest in their default order
+        let mut ordered: Vec<EventColumn> = self
+            .formatting
+            .column_order
+            .iter()
+            .filter(|column| columns.contains(column))
+            .copied()
+            .collect();
+        let rest: Vec<EventColumn> = columns
+            .into_iter()
+            .filter(|column| !ordered.contains(column))
+            .collect();
+        ordered.extend(rest);
+        ordered
+    }
+}
+
+/// A column of the time window CSV
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowColumn {
+    Start,
+    End,
+    Duration,
+    On,
+    Off,
+    Both,
+    ActivePixels,
+    EventRate,
+    PolarityRatio,
+    CentroidX,
+    CentroidY,
+    Spread,
+    MeanInterval,
+}
+
+impl WindowColumn {
+    /// Column selected by its `--columns` name
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "start" => Some(WindowColumn::Start),
+            "end" => Some(WindowColumn::End),
+            "duration" => Some(WindowColumn::Duration),
+            "both" => Some(WindowColumn::Both),
+            "active_pixels" => Some(WindowColumn::ActivePixels),
+            "event_rate" => Some(WindowColumn::EventRate),
+            "polarity_ratio" => Some(WindowColumn::PolarityRatio),
+            "centroid_x" => Some(WindowColumn::CentroidX),
+            "centroid_y" => Some(WindowColumn::CentroidY),
+            "spread" => Some(WindowColumn::Spread),
+            "mean_interval" => Some(WindowColumn::MeanInterval),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn header(self) -> &'static str {
+        match self {
+            WindowColumn::Start => "Start",
+            WindowColumn::End => "End",
+            WindowColumn::Duration => "Duration",
+            WindowColumn::On => "On",
+            WindowColumn::Off => "Off",
+            WindowColumn::Both => "Both",
+            WindowColumn::ActivePixels => "Active_Pixels",
+            WindowColumn::EventRate => "Event_Rate",
+            WindowColumn::PolarityRatio => "Polarity_Ratio",
+            WindowColumn::CentroidX => "Centroid_X",
+            WindowColumn::CentroidY => "Centroid_Y",
+            WindowColumn::Spread => "Spread",
+            WindowColumn::MeanInterval => "Mean_Interval",
+        }
     }
 }
 
+/// How the downscaled image of each window is stored
+pub enum PgmOutput {
+    /// ASCII PGM embedded in the CSV with newlines replaced by dashes
+    String,
+    /// Binary PGM files referenced from the CSV
+    Pgm,
+    /// PNG files referenced from the CSV
+    Png,
+}
+
 pub struct TimeWindowConfig {
     pub filename: PathBuf,
-    pub include_both_column: bool,
+    pub compression: OutputCompression,
+    pub columns: Vec<WindowColumn>,
     pub include_pgm: bool,
+    pub window_mode: WindowMode,
     pub window_size: u32,
+    pub stride: Option<u32>,
     pub max_windows: u32,
     pub pgm_scale: usize,
     pub pgm_threshold: usize,
+    pub pgm_output: PgmOutput,
+    pub pgm_counts: bool,
+    pub transform: Transform,
 }
 
 impl TimeWindowConfig {
-    pub fn new(args: &ArgMatches) -> Result<TimeWindowConfig, std::io::Error> {
-        let mut filename = args.get_one::<PathBuf>("filename").unwrap().clone();
-        filename.set_extension("csv");
+    pub fn new(args: &ArgMatches, paths: &FilePaths) -> Result<TimeWindowConfig, std::io::Error> {
+        let (filename, compression) = text_output(args, paths, |f| f.with_extension("csv"))?;
+
+        let window_mode = if args.get_flag("eventBasedReconstruction") {
+            WindowMode::EventCount
+        } else {
+            WindowMode::Time
+        };
 
         let window_size = args.get_one::<u32>("windowSize").unwrap().to_owned();
+        let stride = args.get_one::<u32>("stride").copied();
 
         let max_windows: u32 = match args.get_one::<u32>("maxWindows") {
             Some(v) => v.to_owned(),
-            None => std::u32::MAX,
+            None => u32::MAX,
         };
 
-        let include_both_column = args.get_flag("includeBoth");
+        // Event based windows vary in duration, so their timing is always included
+        let mut columns = match window_mode {
+            WindowMode::Time => vec![WindowColumn::On, WindowColumn::Off],
+            WindowMode::EventCount => vec![
+                WindowColumn::Start,
+                WindowColumn::End,
+                WindowColumn::Duration,
+                WindowColumn::On,
+                WindowColumn::Off,
+            ],
+        };
+
+        let mut extra_columns = Vec::new();
+        if args.get_flag("includeBoth") {
+            extra_columns.push(WindowColumn::Both);
+        }
+        for name in args.get_many::<String>("columns").into_iter().flatten() {
+            extra_columns.push(WindowColumn::from_name(name).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Unknown column '{name}'"),
+                )
+            })?);
+        }
+        for column in extra_columns {
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
 
         let include_pgm = args.get_flag("includePgm");
         let pgm_scale = match args.get_one::<usize>("pgmScale") {
@@ -84,27 +606,52 @@ impl TimeWindowConfig {
             None => 0,
         };
 
+        let pgm_output = match args.get_one::<String>("pgmFormat").map(String::as_str) {
+            Some("pgm") => PgmOutput::Pgm,
+            Some("png") => PgmOutput::Png,
+            _ => PgmOutput::String,
+        };
+        if include_pgm && is_stdio(&filename) && !matches!(pgm_output, PgmOutput::String) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Image files are written next to the CSV, which requires an output file",
+            ));
+        }
+        let pgm_counts = args.get_flag("pgmCounts");
+
+        let transform = parse_transform(args);
+
         Ok(TimeWindowConfig {
             filename,
-            include_both_column,
+            compression,
+            columns,
             include_pgm,
+            window_mode,
             window_size,
+            stride,
             max_windows,
             pgm_scale,
             pgm_threshold,
+            pgm_output,
+            pgm_counts,
+            transform,
         })
     }
 
     #[must_use]
     pub fn create_csv_header(&self) -> String {
-        let mut header_tmp = String::from("On,Off");
-
-        if self.include_both_column {
-            header_tmp.push_str(",Both");
-        }
+        let mut header_tmp = self
+            .columns
+            .iter()
+            .map(|column| column.header())
+            .collect::<Vec<_>>()
+            .join(",");
 
         if self.include_pgm {
-            header_tmp.push_str(",PGM_String");
+            match self.pgm_output {
+                PgmOutput::String => header_tmp.push_str(",PGM_String"),
+                PgmOutput::Pgm | PgmOutput::Png => header_tmp.push_str(",PGM_File"),
+            }
         }
 
         header_tmp.push('\n');
@@ -112,26 +659,65 @@ impl TimeWindowConfig {
     }
 }
 
+pub enum RenderMode {
+    LastEvent,
+    Decay {
+        time_constant: u32,
+    },
+    TimeSurface {
+        time_constant: u32,
+    },
+    Counts {
+        signed: bool,
+        normalization: CountNormalization,
+    },
+}
+
 pub struct VidConfig {
     pub filename: PathBuf,
     pub window_size: usize,
+    pub stride: Option<usize>,
     pub max_frames: usize,
     pub exclude_on: bool,
     pub exclude_off: bool,
     pub keep_frames: bool,
     pub omit_video: bool,
+    pub transform: Transform,
+    pub calibration: Option<PathBuf>,
+    pub render_mode: RenderMode,
+    pub fourcc: [char; 4],
+    pub container: String,
+    pub fps: f64,
+    pub scale: u32,
+    pub palette: Palette,
+    pub colormap: Colormap,
+    pub save_counts: bool,
+    pub overlay: bool,
+    pub scale_bar: Option<u32>,
+    pub threads: usize,
 }
 
 impl VidConfig {
-    pub fn new(args: &ArgMatches) -> Result<VidConfig, std::io::Error> {
-        let mut filename = args.get_one::<PathBuf>("filename").unwrap().clone();
-        filename.set_extension("");
+    pub fn new(args: &ArgMatches, paths: &FilePaths) -> Result<VidConfig, std::io::Error> {
+        // An extension on --output selects the container of the video
+        let filename = output_filename(paths, false, |f| f.with_extension(""))?;
+        let container_extension = paths
+            .output
+            .as_ref()
+            .and_then(|output| output.extension())
+            .map(|extension| extension.to_string_lossy().into_owned());
+        let filename = match container_extension {
+            Some(_) => filename.with_extension(""),
+            None => filename,
+        };
 
         let window_size: usize = args.get_one::<usize>("windowSize").unwrap().to_owned();
 
+        let stride = args.get_one::<usize>("stride").copied();
+
         let max_frames: usize = match args.get_one::<usize>("maxFrames") {
             Some(v) => v.to_owned(),
-            None => std::usize::MAX,
+            None => usize::MAX,
         };
 
         let exclude_on = args.get_flag("excludeOnEvents");
@@ -139,15 +725,442 @@ impl VidConfig {
 
         let keep_frames = args.get_flag("keepFrames");
         let omit_video = args.get_flag("omitVideo");
+        let transform = parse_transform(args);
+        let calibration = args.get_one::<PathBuf>("calibration").cloned();
+
+        let render_mode = match args.get_one::<String>("renderMode").map(String::as_str) {
+            Some("decay") => RenderMode::Decay {
+                time_constant: args.get_one::<u32>("decayTime").unwrap().to_owned(),
+            },
+            Some("time_surface") => RenderMode::TimeSurface {
+                time_constant: args.get_one::<u32>("decayTime").unwrap().to_owned(),
+            },
+            Some("counts") => RenderMode::Counts {
+                signed: args.get_one::<String>("countMode").map(String::as_str) == Some("signed"),
+                normalization: match args.get_one::<u32>("countClip") {
+                    Some(clip) => CountNormalization::Clip(clip.to_owned()),
+                    None => CountNormalization::Max,
+                },
+            },
+            _ => RenderMode::LastEvent,
+        };
+
+        // Custom colors override the colors of the selected palette
+        let mut palette =
+            Palette::from_name(args.get_one::<String>("palette").unwrap()).unwrap_or_default();
+        if let Some(hex) = args.get_one::<String>("onColor") {
+            palette.on = parse_hex_color(hex)?;
+        }
+        if let Some(hex) = args.get_one::<String>("offColor") {
+            palette.off = parse_hex_color(hex)?;
+        }
+        if let Some(hex) = args.get_one::<String>("backgroundColor") {
+            palette.background = parse_hex_color(hex)?;
+        }
+
+        let colormap = match args.get_one::<String>("colormap").map(String::as_str) {
+            Some("inferno") => Colormap::Inferno,
+            Some("grey") => Colormap::Grey,
+            _ => Colormap::Viridis,
+        };
+
+        let codec = args.get_one::<String>("codec").unwrap();
+        let fourcc: [char; 4] = match codec.chars().collect::<Vec<_>>().try_into() {
+            Ok(fourcc) if codec.is_ascii() => fourcc,
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Codec '{codec}' is not a four character code"),
+                ))
+            }
+        };
+
+        let container = container_extension.unwrap_or_else(|| {
+            args.get_one::<String>("container")
+                .unwrap()
+                .trim_start_matches('.')
+                .to_owned()
+        });
+
+        // Real-time playback shows one time window per window duration
+        let fps = match args.get_one::<String>("fps").unwrap().as_str() {
+            "real_time" if args.get_flag("timeBasedReconstruction") => {
+                1_000_000.0 / window_size as f64
+            }
+            "real_time" => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Real-time frame rate requires time based reconstruction",
+                ))
+            }
+            fps => match fps.parse::<f64>() {
+                Ok(fps) if fps > 0.0 && fps.is_finite() => fps,
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("Invalid frame rate '{fps}'"),
+                    ))
+                }
+            },
+        };
+
+        let scale = args.get_one::<u32>("scale").unwrap().to_owned();
+
+        let save_counts = args.get_flag("saveCounts");
+        if save_counts && !matches!(render_mode, RenderMode::Counts { .. }) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Saving counts requires the counts render mode",
+            ));
+        }
+
+        let overlay = args.get_flag("overlay");
+        let scale_bar = args.get_one::<u32>("scaleBar").copied();
+
+        // Zero threads uses the current thread pool: one thread per core, or the --jobs
+        // threads of a batch conversion
+        let threads = args.get_one::<usize>("threads").copied().unwrap_or(0);
 
         Ok(VidConfig {
             filename,
             window_size,
+            stride,
             max_frames,
             exclude_on,
             exclude_off,
             keep_frames,
             omit_video,
+            transform,
+            calibration,
+            render_mode,
+            fourcc,
+            container,
+            fps,
+            scale,
+            palette,
+            colormap,
+            save_counts,
+            overlay,
+            scale_bar,
+            threads,
+        })
+    }
+}
+
+pub enum SurfaceFormat {
+    Npy,
+    Png,
+}
+
+pub struct TimeSurfaceConfig {
+    pub filename: PathBuf,
+    pub interval: u32,
+    pub time_constant: u32,
+    pub max_surfaces: usize,
+    pub format: SurfaceFormat,
+    pub transform: Transform,
+}
+
+impl TimeSurfaceConfig {
+    pub fn new(args: &ArgMatches, paths: &FilePaths) -> Result<TimeSurfaceConfig, std::io::Error> {
+        // Surfaces are written to a directory named after the input file
+        let filename = output_filename(paths, false, |mut f| {
+            f.set_extension("");
+            f.as_mut_os_string().push("_time_surfaces");
+            f
+        })?;
+
+        let interval = args.get_one::<u32>("interval").unwrap().to_owned();
+        let time_constant = args.get_one::<u32>("decayTime").unwrap().to_owned();
+
+        let max_surfaces: usize = match args.get_one::<usize>("maxSurfaces") {
+            Some(v) => v.to_owned(),
+            None => usize::MAX,
+        };
+
+        let format = match args.get_one::<String>("format").map(String::as_str) {
+            Some("png") => SurfaceFormat::Png,
+            _ => SurfaceFormat::Npy,
+        };
+
+        let transform = parse_transform(args);
+
+        Ok(TimeSurfaceConfig {
+            filename,
+            interval,
+            time_constant,
+            max_surfaces,
+            format,
+            transform,
+        })
+    }
+}
+
+pub enum WindowMode {
+    Time,
+    EventCount,
+}
+
+pub enum TensorKind {
+    VoxelGrid { bins: usize },
+    Histogram,
+}
+
+pub enum TensorFormat {
+    Npy,
+    Npz { compressed: bool },
+}
+
+pub struct TensorConfig {
+    pub filename: PathBuf,
+    pub window_mode: WindowMode,
+    pub window_size: usize,
+    pub max_windows: usize,
+    pub kind: TensorKind,
+    pub format: TensorFormat,
+    pub transform: Transform,
+}
+
+impl TensorConfig {
+    pub fn new(args: &ArgMatches, paths: &FilePaths) -> Result<TensorConfig, std::io::Error> {
+        let format = match args.get_one::<String>("format").map(String::as_str) {
+            Some("npy") => TensorFormat::Npy,
+            _ => TensorFormat::Npz {
+                compressed: args.get_flag("compressNpz"),
+            },
+        };
+
+        // npy tensors are written to a directory, npz tensors to a single archive
+        let filename = output_filename(paths, false, |mut f| {
+            f.set_extension("");
+            match format {
+                TensorFormat::Npy => f.as_mut_os_string().push("_tensors"),
+                TensorFormat::Npz { .. } => f.as_mut_os_string().push("_tensors.npz"),
+            }
+            f
+        })?;
+
+        let window_mode = if args.get_flag("timeBasedReconstruction") {
+            WindowMode::Time
+        } else {
+            WindowMode::EventCount
+        };
+
+        let window_size = args.get_one::<usize>("windowSize").unwrap().to_owned();
+        if window_size == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Window size must be greater than 0",
+            ));
+        }
+
+        let max_windows: usize = match args.get_one::<usize>("maxWindows") {
+            Some(v) => v.to_owned(),
+            None => usize::MAX,
+        };
+
+        let kind = match args.get_one::<String>("representation").map(String::as_str) {
+            Some("histogram") => TensorKind::Histogram,
+            _ => match args.get_one::<usize>("bins").unwrap().to_owned() {
+                0 => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "Number of bins must be greater than 0",
+                    ))
+                }
+                bins => TensorKind::VoxelGrid { bins },
+            },
+        };
+
+        let transform = parse_transform(args);
+
+        Ok(TensorConfig {
+            filename,
+            window_mode,
+            window_size,
+            max_windows,
+            kind,
+            format,
+            transform,
+        })
+    }
+}
+
+/// Layout of a CSV file of events
+pub struct CsvInputConfig {
+    /// Meaning of each column. `None` marks a column that is ignored
+    pub columns: Vec<Option<EventColumn>>,
+    pub delimiter: char,
+    pub time_unit: TimeUnit,
+    pub polarity: PolarityEncoding,
+    /// Coordinate of the first pixel, 0 or 1
+    pub coord_base: u8,
+    pub camera_type: CameraType,
+}
+
+impl CsvInputConfig {
+    /// CSV input is selected by giving the size of the sensor that recorded the events
+    pub fn new(args: &ArgMatches) -> Result<Option<CsvInputConfig>, std::io::Error> {
+        let camera_type = match args.get_one::<String>("sensorSize").map(String::as_str) {
+            None => return Ok(None),
+            Some("128x128") => CameraType::DVS128,
+            Some("240x180") => CameraType::DAVIS240,
+            Some(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "The sensor size must be 128x128 (DVS128) or 240x180 (DAVIS240)",
+                ))
+            }
+        };
+
+        let columns: Vec<Option<EventColumn>> = args
+            .get_many::<String>("csvColumns")
+            .unwrap()
+            .map(|name| EventColumn::from_name(name))
+            .collect();
+
+        for column in [
+            EventColumn::Timestamp,
+            EventColumn::X,
+            EventColumn::Y,
+            EventColumn::Polarity,
+        ] {
+            if columns.iter().filter(|c| **c == Some(column)).count() != 1 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "--csv_columns must list each of t, x, y and p once",
+                ));
+            }
+        }
+
+        Ok(Some(CsvInputConfig {
+            columns,
+            delimiter: parse_delimiter(args.get_one::<String>("csvDelimiter").unwrap())?,
+            time_unit: parse_time_unit(args, "csvTimeUnit"),
+            polarity: parse_polarity_encoding(args, "csvPolarity"),
+            coord_base: args.get_one::<u8>("csvCoordBase").unwrap().to_owned(),
+            camera_type,
+        }))
+    }
+}
+
+/// Where the events of a subcommand are read from
+pub struct InputConfig {
+    pub filename: PathBuf,
+    /// Layout of the input when it is a CSV file
+    pub csv: Option<CsvInputConfig>,
+    /// Timestamps of the events to convert, given with --start and --end
+    pub time_range: Option<Range<i32>>,
+}
+
+impl InputConfig {
+    pub fn new(args: &ArgMatches, paths: &FilePaths) -> Result<InputConfig, std::io::Error> {
+        let filename = paths.input.clone();
+        let csv = CsvInputConfig::new(args)?;
+
+        let is_csv_file = strip_compression_extension(&filename)
+            .extension()
+            .is_some_and(|extension| extension == "csv");
+        if is_csv_file && csv.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "CSV input requires --sensor_size",
+            ));
+        }
+
+        let time_range = match (
+            args.get_one::<i32>("startTime"),
+            args.get_one::<i32>("endTime"),
+        ) {
+            (None, None) => None,
+            (start, end) => Some(*start.unwrap_or(&i32::MIN)..*end.unwrap_or(&i32::MAX)),
+        };
+        if time_range.as_ref().is_some_and(Range::is_empty) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--end must be greater than --start",
+            ));
+        }
+
+        Ok(InputConfig {
+            filename,
+            csv,
+            time_range,
+        })
+    }
+}
+
+pub struct AedatConfig {
+    pub filename: PathBuf,
+}
+
+impl AedatConfig {
+    pub fn new(paths: &FilePaths) -> Result<AedatConfig, std::io::Error> {
+        let filename = output_filename(paths, false, |f| f.with_extension("aedat"))?;
+
+        Ok(AedatConfig { filename })
+    }
+}
+
+pub struct CompactConfig {
+    pub filename: PathBuf,
+    pub options: CompactOptions,
+}
+
+impl CompactConfig {
+    pub fn new(args: &ArgMatches, paths: &FilePaths) -> Result<CompactConfig, std::io::Error> {
+        let filename = output_filename(paths, false, |f| f.with_extension("aedc"))?;
+
+        let block_size = args.get_one::<usize>("blockSize").unwrap().to_owned();
+        if block_size == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Block size must be greater than 0",
+            ));
+        }
+
+        let compression = args.get_one::<u8>("compression").unwrap().to_owned();
+
+        Ok(CompactConfig {
+            filename,
+            options: CompactOptions {
+                block_size,
+                compression,
+            },
+        })
+    }
+}
+
+#[cfg(feature = "hdf5")]
+pub struct Hdf5Config {
+    pub filename: PathBuf,
+    pub compression: u8,
+    pub chunk_size: usize,
+    pub transform: Transform,
+}
+
+#[cfg(feature = "hdf5")]
+impl Hdf5Config {
+    pub fn new(args: &ArgMatches, paths: &FilePaths) -> Result<Hdf5Config, std::io::Error> {
+        let filename = output_filename(paths, false, |f| f.with_extension("h5"))?;
+
+        let compression = args.get_one::<u8>("compression").unwrap().to_owned();
+
+        let chunk_size = args.get_one::<usize>("chunkSize").unwrap().to_owned();
+        if chunk_size == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Chunk size must be greater than 0",
+            ));
+        }
+
+        let transform = parse_transform(args);
+
+        Ok(Hdf5Config {
+            filename,
+            compression,
+            chunk_size,
+            transform,
         })
     }
 }
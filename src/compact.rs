@@ -0,0 +1,394 @@
+//! Compact binary event format.
+//!
+//! Stores decoded events so that they can be reloaded without parsing the AEDAT header or
+//! byte-swapping every event. All integers are little endian.
+//!
+//! ```text
+//! Header
+//!   magic          4 bytes  "AEDC"
+//!   version        u8       1
+//!   camera         u8       0 = DVS128, 1 = DAVIS240
+//!   compression    u8       0 = none, 1 = zstd
+//!   reserved       u8
+//!   event count    u64
+//!   block count    u32
+//!   header length  u32
+//!   aedat header   ASCII header of the source AEDAT file, header length bytes
+//! Block index, one entry per block
+//!   first time     i32      timestamp of the first event in the block
+//!   min time       i32      smallest timestamp in the block
+//!   max time       i32      largest timestamp in the block
+//!   event count    u32
+//!   offset         u64      position of the block from the start of the file
+//!   length         u32      stored (compressed) length of the block
+//! Blocks
+//! ```
+//!
+//! Each event in a decompressed block is the zigzag LEB128 varint of the difference between
+//! its timestamp and the timestamp of the previous event (the first time of the block for the
+//! first event), followed by three bytes holding `((y * width + x) << 1) | polarity` with
+//! 0-based coordinates. Only the coordinates, polarity and timestamp of each event are kept
+
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use crate::aedat_data::{CameraParameters, CameraType, Event};
+
+pub const MAGIC: &[u8; 4] = b"AEDC";
+const VERSION: u8 = 1;
+
+/// Size of the fixed part of the header in bytes
+const HEADER_SIZE: u64 = 24;
+/// Size of a block index entry in bytes
+const INDEX_ENTRY_SIZE: u64 = 28;
+
+pub struct CompactOptions {
+    /// Number of events in each block
+    pub block_size: usize,
+    /// Zstd compression level of the blocks. 0 stores blocks uncompressed
+    pub compression: u8,
+}
+
+/// Location and time span of a block of events
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockInfo {
+    pub first_time: i32,
+    pub min_time: i32,
+    pub max_time: i32,
+    pub event_count: u32,
+    pub offset: u64,
+    pub length: u32,
+}
+
+/// Returns true if the data starts with the magic bytes of the compact format
+#[must_use]
+pub fn is_compact(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn camera_code(cam_type: &CameraType) -> u8 {
+    match cam_type {
+        CameraType::DVS128 => 0,
+        CameraType::DAVIS240 => 1,
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> io::Result<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *data
+            .get(*pos)
+            .ok_or_else(|| invalid_data("Truncated event block"))?;
+        *pos += 1;
+
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(invalid_data("Invalid timestamp delta"))
+}
+
+fn encode_block(events: &[Event], cam: &CameraParameters) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(events.len() * 4);
+    let mut previous = events.first().map_or(0, Event::get_timestamp);
+
+    for event in events {
+        let timestamp = event.get_timestamp();
+        let delta = i64::from(timestamp) - i64::from(previous);
+        write_varint(&mut buf, ((delta << 1) ^ (delta >> 63)) as u64);
+        previous = timestamp;
+
+        let (x, y) = event.get_coords(&cam.camera_type);
+        if x == 0 || y == 0 || x > cam.camera_x || y > cam.camera_y {
+            return Err(invalid_data("Event coordinates are outside of the sensor"));
+        }
+
+        let pixel = u32::from(y - 1) * u32::from(cam.camera_x) + u32::from(x - 1);
+        let packed = (pixel << 1) | u32::from(event.get_polarity(&cam.camera_type));
+        buf.extend_from_slice(&packed.to_le_bytes()[..3]);
+    }
+
+    Ok(buf)
+}
+
+fn decode_block(
+    data: &[u8],
+    block: &BlockInfo,
+    cam: &CameraParameters,
+    events: &mut Vec<Event>,
+) -> io::Result<()> {
+    let mut pos = 0;
+    let mut timestamp = i64::from(block.first_time);
+
+    for _ in 0..block.event_count {
+        let zigzag = read_varint(data, &mut pos)?;
+        timestamp += (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+
+        let packed = data
+            .get(pos..pos + 3)
+            .ok_or_else(|| invalid_data("Truncated event block"))?;
+        pos += 3;
+        let packed = u32::from_le_bytes([packed[0], packed[1], packed[2], 0]);
+
+        let pixel = packed >> 1;
+        let x = pixel % u32::from(cam.camera_x) + 1;
+        let y = pixel / u32::from(cam.camera_x) + 1;
+        if y > u32::from(cam.camera_y) {
+            return Err(invalid_data("Event coordinates are outside of the sensor"));
+        }
+
+        let timestamp = i32::try_from(timestamp).map_err(|_| invalid_data("Invalid timestamp"))?;
+        events.push(Event::new(
+            x as u8,
+            y as u8,
+            packed & 1 == 1,
+            timestamp,
+            &cam.camera_type,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Write events in the compact format
+pub fn write_compact<W: Write>(
+    writer: &mut W,
+    events: &[Event],
+    header: &str,
+    cam: &CameraParameters,
+    options: &CompactOptions,
+) -> io::Result<()> {
+    if options.block_size == 0 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "Block size must be greater than 0",
+        ));
+    }
+
+    let blocks = events
+        .chunks(options.block_size)
+        .map(|chunk| {
+            let data = encode_block(chunk, cam)?;
+            if options.compression > 0 {
+                zstd::bulk::compress(&data, i32::from(options.compression))
+            } else {
+                Ok(data)
+            }
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut offset = HEADER_SIZE + header.len() as u64 + INDEX_ENTRY_SIZE * blocks.len() as u64;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[
+        VERSION,
+        camera_code(&cam.camera_type),
+        u8::from(options.compression > 0),
+        0,
+    ])?;
+    writer.write_all(&(events.len() as u64).to_le_bytes())?;
+    writer.write_all(&(blocks.len() as u32).to_le_bytes())?;
+    writer.write_all(&(header.len() as u32).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+
+    for (chunk, data) in events.chunks(options.block_size).zip(&blocks) {
+        let timestamps = chunk.iter().map(Event::get_timestamp);
+
+        writer.write_all(&chunk[0].get_timestamp().to_le_bytes())?;
+        writer.write_all(&timestamps.clone().min().unwrap_or(0).to_le_bytes())?;
+        writer.write_all(&timestamps.max().unwrap_or(0).to_le_bytes())?;
+        writer.write_all(&(chunk.len() as u32).to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+        writer.write_all(&(data.len() as u32).to_le_bytes())?;
+
+        offset += data.len() as u64;
+    }
+
+    for data in &blocks {
+        writer.write_all(data)?;
+    }
+
+    Ok(())
+}
+
+/// Reader for compact event files that loads blocks on demand
+pub struct CompactReader<R> {
+    reader: R,
+    cam: CameraParameters,
+    compressed: bool,
+    event_count: u64,
+    header: String,
+    blocks: Vec<BlockInfo>,
+}
+
+impl<R: Read + Seek> CompactReader<R> {
+    /// Read the header and block index
+    pub fn new(mut reader: R) -> io::Result<CompactReader<R>> {
+        let mut fixed = [0u8; HEADER_SIZE as usize];
+        reader.read_exact(&mut fixed)?;
+
+        if !is_compact(&fixed) {
+            return Err(invalid_data("Not a compact event file"));
+        }
+        if fixed[4] != VERSION {
+            return Err(invalid_data("Unsupported compact event file version"));
+        }
+
+        let camera_type = match fixed[5] {
+            0 => CameraType::DVS128,
+            1 => CameraType::DAVIS240,
+            _ => return Err(invalid_data("Unknown camera type")),
+        };
+        let compressed = match fixed[6] {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid_data("Unknown block compression")),
+        };
+
+        let event_count = u64::from_le_bytes(fixed[8..16].try_into().unwrap());
+        let block_count = u32::from_le_bytes(fixed[16..20].try_into().unwrap());
+        let header_len = u32::from_le_bytes(fixed[20..24].try_into().unwrap());
+
+        // Sizes are checked against the length of the file before any buffers are allocated
+        let stream_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(HEADER_SIZE))?;
+        let index_len = u64::from(block_count) * INDEX_ENTRY_SIZE;
+        if HEADER_SIZE + u64::from(header_len) + index_len > stream_len {
+            return Err(invalid_data("Truncated compact event file"));
+        }
+
+        let mut header = vec![0u8; header_len as usize];
+        reader.read_exact(&mut header)?;
+        let header = String::from_utf8_lossy(&header).into_owned();
+
+        let mut index = vec![0u8; index_len as usize];
+        reader.read_exact(&mut index)?;
+
+        let blocks: Vec<BlockInfo> = index
+            .chunks(INDEX_ENTRY_SIZE as usize)
+            .map(|entry| BlockInfo {
+                first_time: i32::from_le_bytes(entry[0..4].try_into().unwrap()),
+                min_time: i32::from_le_bytes(entry[4..8].try_into().unwrap()),
+                max_time: i32::from_le_bytes(entry[8..12].try_into().unwrap()),
+                event_count: u32::from_le_bytes(entry[12..16].try_into().unwrap()),
+                offset: u64::from_le_bytes(entry[16..24].try_into().unwrap()),
+                length: u32::from_le_bytes(entry[24..28].try_into().unwrap()),
+            })
+            .collect();
+
+        if blocks.iter().any(|block| {
+            block
+                .offset
+                .checked_add(u64::from(block.length))
+                .is_none_or(|end| end > stream_len)
+        }) {
+            return Err(invalid_data("Truncated compact event file"));
+        }
+        if blocks
+            .iter()
+            .map(|block| u64::from(block.event_count))
+            .sum::<u64>()
+            != event_count
+        {
+            return Err(invalid_data("Event count does not match the block index"));
+        }
+
+        Ok(CompactReader {
+            reader,
+            cam: CameraParameters::new(camera_type),
+            compressed,
+            event_count,
+            header,
+            blocks,
+        })
+    }
+
+    #[must_use]
+    pub fn camera(&self) -> &CameraParameters {
+        &self.cam
+    }
+
+    /// ASCII header of the AEDAT file the events were read from
+    #[must_use]
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn event_count(&self) -> u64 {
+        self.event_count
+    }
+
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn blocks(&self) -> &[BlockInfo] {
+        &self.blocks
+    }
+
+    /// Decode a single block
+    pub fn read_block(&mut self, index: usize) -> io::Result<Vec<Event>> {
+        let block = self
+            .blocks
+            .get(index)
+            .cloned()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Block does not exist"))?;
+
+        let mut data = vec![0u8; block.length as usize];
+        self.reader.seek(SeekFrom::Start(block.offset))?;
+        self.reader.read_exact(&mut data)?;
+
+        if self.compressed {
+            data = zstd::decode_all(data.as_slice())?;
+        }
+
+        // Every event takes at least one byte of varint and three bytes of coordinates
+        let mut events = Vec::with_capacity((block.event_count as usize).min(data.len() / 4));
+        decode_block(&data, &block, &self.cam, &mut events)?;
+        Ok(events)
+    }
+
+    /// Decode all events
+    pub fn read_events(&mut self) -> io::Result<Vec<Event>> {
+        let mut events = Vec::new();
+        for index in 0..self.blocks.len() {
+            events.append(&mut self.read_block(index)?);
+        }
+        Ok(events)
+    }
+
+    /// Decode the events with timestamps in `start_time..end_time`, only reading the blocks
+    /// that overlap that time range
+    pub fn read_time_range(&mut self, start_time: i32, end_time: i32) -> io::Result<Vec<Event>> {
+        let mut events = Vec::new();
+
+        for index in 0..self.blocks.len() {
+            let block = &self.blocks[index];
+            if block.max_time < start_time || block.min_time >= end_time {
+                continue;
+            }
+
+            events.extend(
+                self.read_block(index)?
+                    .into_iter()
+                    .filter(|event| (start_time..end_time).contains(&event.get_timestamp())),
+            );
+        }
+
+        Ok(events)
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+
+/// Compression of a text output stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl OutputCompression {
+    /// Compression selected by the extension of a filename, e.g. `events.csv.gz`
+    #[must_use]
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => OutputCompression::Gzip,
+            Some("zst") => OutputCompression::Zstd,
+            _ => OutputCompression::None,
+        }
+    }
+
+    #[must_use]
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            OutputCompression::None => None,
+            OutputCompression::Gzip => Some("gz"),
+            OutputCompression::Zstd => Some("zst"),
+        }
+    }
+
+    /// Append the extension of the compression to a filename
+    #[must_use]
+    pub fn apply_extension(&self, path: PathBuf) -> PathBuf {
+        match self.extension() {
+            Some(extension) if OutputCompression::from_path(&path) != *self => {
+                let mut path = path.into_os_string();
+                path.push(".");
+                path.push(extension);
+                path.into()
+            }
+            _ => path,
+        }
+    }
+}
+
+/// Filename without its compression extension, e.g. `events.csv` for `events.csv.gz`
+#[must_use]
+pub fn strip_compression_extension(path: &Path) -> PathBuf {
+    match OutputCompression::from_path(path) {
+        OutputCompression::None => path.to_path_buf(),
+        _ => path.with_extension(""),
+    }
+}
+
+/// Filename that stands for standard input or standard output
+pub const STDIO: &str = "-";
+
+#[must_use]
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO)
+}
+
+type OutputSink = BufWriter<Box<dyn Write>>;
+
+/// Buffered output file or standard output, optionally compressed
+pub enum OutputWriter {
+    Plain(OutputSink),
+    Gzip(GzEncoder<OutputSink>),
+    Zstd(zstd::Encoder<'static, OutputSink>),
+}
+
+impl OutputWriter {
+    /// Create the output file, or write to standard output if the path is `-`
+    pub fn create(path: &Path, compression: OutputCompression) -> io::Result<OutputWriter> {
+        let sink: Box<dyn Write> = if is_stdio(path) {
+            Box::new(io::stdout().lock())
+        } else {
+            Box::new(File::create(path)?)
+        };
+        let sink = BufWriter::new(sink);
+
+        Ok(match compression {
+            OutputCompression::None => OutputWriter::Plain(sink),
+            OutputCompression::Gzip => {
+                OutputWriter::Gzip(GzEncoder::new(sink, flate2::Compression::default()))
+            }
+            OutputCompression::Zstd => OutputWriter::Zstd(zstd::Encoder::new(sink, 0)?),
+        })
+    }
+
+    /// Write the end of the compressed stream and flush the output
+    pub fn finish(self) -> io::Result<()> {
+        let mut sink = match self {
+            OutputWriter::Plain(sink) => sink,
+            OutputWriter::Gzip(encoder) => encoder.finish()?,
+            OutputWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        sink.flush()
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(sink) => sink.write(buf),
+            OutputWriter::Gzip(encoder) => encoder.write(buf),
+            OutputWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(sink) => sink.flush(),
+            OutputWriter::Gzip(encoder) => encoder.flush(),
+            OutputWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Decompress data that starts with a gzip or zstd magic number. Other data is returned as is
+pub fn decompress_input(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+    const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+    if data.starts_with(&GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(data.as_slice()).read_to_end(&mut decompressed)?;
+        Ok(decompressed)
+    } else if data.starts_with(&ZSTD_MAGIC) {
+        zstd::decode_all(data.as_slice())
+    } else {
+        Ok(data)
+    }
+}
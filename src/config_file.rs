@@ -0,0 +1,308 @@
+//! TOML config files holding the options of conversions.
+//!
+//! Settings are keyed by the long names of the command line options, e.g. `window_size = 10000`
+//! or `coords = true`. Top level settings apply to every subcommand that accepts them, and a
+//! table named after a subcommand, e.g. `[vid]`, holds settings of that subcommand only. Options
+//! given on the command line take precedence over the file
+//!
+//! ```toml
+//! flip_x = true
+//!
+//! [csv]
+//! coords = true
+//! include_polarity = true
+//! delimiter = "tab"
+//!
+//! [vid]
+//! time_based = true
+//! window_size = 10000
+//! palette = "red_blue"
+//! ```
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, Command, Id};
+use toml::{Table, Value};
+
+/// Options that cannot be set in a config file
+const EXCLUDED_IDS: [&str; 3] = ["config", "printConfig", "help"];
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Option of a subcommand that a config file setting refers to
+fn find_setting<'a>(cmd: &'a Command, key: &str) -> Option<&'a Arg> {
+    cmd.get_arguments()
+        .find(|arg| arg.get_long() == Some(key) && !EXCLUDED_IDS.contains(&arg.get_id().as_str()))
+}
+
+fn is_flag(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::SetTrue)
+}
+
+fn is_list(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Append)
+}
+
+pub struct ConfigFile {
+    table: Table,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> io::Result<ConfigFile> {
+        let text = fs::read_to_string(path)?;
+        let table = text
+            .parse::<Table>()
+            .map_err(|err| invalid_data(err.to_string()))?;
+
+        Ok(ConfigFile { table })
+    }
+
+    /// Check that every setting is accepted by its subcommand, or by any subcommand for top
+    /// level settings
+    fn validate(&self, command: &Command) -> io::Result<()> {
+        for (key, value) in &self.table {
+            match value {
+                Value::Table(table) => {
+                    let cmd = command.find_subcommand(key).ok_or_else(|| {
+                        invalid_data(format!("[{key}] does not name a subcommand"))
+                    })?;
+
+                    if let Some(key) = table.keys().find(|key| find_setting(cmd, key).is_none()) {
+                        return Err(invalid_data(format!(
+                            "Unknown setting {key} in [{}]",
+                            cmd.get_name()
+                        )));
+                    }
+                }
+                _ => {
+                    if !command
+                        .get_subcommands()
+                        .any(|cmd| find_setting(cmd, key).is_some())
+                    {
+                        return Err(invalid_data(format!("Unknown setting {key}")));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Settings that apply to a subcommand. Settings in its table replace top level settings
+    fn settings<'a>(&self, cmd: &'a Command) -> Vec<(&'a Arg, Value)> {
+        let mut settings: Table = self
+            .table
+            .iter()
+            .filter(|(_, value)| !value.is_table())
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        if let Some(table) = self.table.get(cmd.get_name()).and_then(Value::as_table) {
+            settings.extend(table.clone());
+        }
+
+        settings
+            .into_iter()
+            .filter_map(|(key, value)| Some((find_setting(cmd, &key)?, value)))
+            .collect()
+    }
+}
+
+/// Command line arguments that set an option to the value of a setting. Settings equal to the
+/// default of the option are left out, so that printed configs can be loaded again
+fn setting_args(arg: &Arg, value: &Value) -> io::Result<Vec<OsString>> {
+    let long = arg.get_long().unwrap();
+
+    let scalar = |value: &Value| match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        _ => Err(invalid_data(format!("Invalid value for {long}"))),
+    };
+
+    let values = match value {
+        _ if is_flag(arg) => {
+            return match value {
+                Value::Boolean(true) => Ok(vec![format!("--{long}").into()]),
+                Value::Boolean(false) => Ok(Vec::new()),
+                _ => Err(invalid_data(format!("{long} must be true or false"))),
+            }
+        }
+        Value::Array(values) if is_list(arg) => {
+            values.iter().map(scalar).collect::<io::Result<Vec<_>>>()?
+        }
+        Value::Array(_) => return Err(invalid_data(format!("{long} does not take a list"))),
+        value => vec![scalar(value)?],
+    };
+
+    let defaults = arg
+        .get_default_values()
+        .iter()
+        .map(|value| value.to_string_lossy())
+        .collect::<Vec<_>>();
+    if values.join(",") == defaults.join(",") {
+        return Ok(Vec::new());
+    }
+
+    Ok(values
+        .iter()
+        .map(|value| format!("--{long}={value}").into())
+        .collect())
+}
+
+/// Options of a subcommand that conflict with an option given on the command line
+fn overridden_ids(cmd: &Command, explicit: &HashSet<Id>) -> HashSet<Id> {
+    let mut overridden = explicit.clone();
+
+    for arg in cmd.get_arguments() {
+        let conflicts = cmd.get_arg_conflicts_with(arg);
+        if explicit.contains(arg.get_id()) {
+            overridden.extend(conflicts.iter().map(|arg| arg.get_id().clone()));
+        } else if conflicts
+            .iter()
+            .any(|other| explicit.contains(other.get_id()))
+        {
+            overridden.insert(arg.get_id().clone());
+        }
+    }
+
+    // Only one option of an exclusive group, such as --time_based and --event_based, can be set
+    for group in cmd.get_groups() {
+        if !group.clone().is_multiple() && group.get_args().any(|id| explicit.contains(id)) {
+            overridden.extend(group.get_args().cloned());
+        }
+    }
+
+    overridden
+}
+
+/// Insert the settings of the config file given with --config into the command line
+/// arguments, leaving out options that are given on the command line
+pub fn apply_config_file(command: &Command, args: Vec<OsString>) -> io::Result<Vec<OsString>> {
+    // Missing options may be filled in by the config file, so errors are reported later
+    let Ok(matches) = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+    else {
+        return Ok(args);
+    };
+    let Some((name, sub_matches)) = matches.subcommand() else {
+        return Ok(args);
+    };
+    let Some(path) = sub_matches.get_one::<PathBuf>("config") else {
+        return Ok(args);
+    };
+    let cmd = command.find_subcommand(name).unwrap();
+
+    let config = ConfigFile::load(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+    config.validate(command)?;
+
+    let explicit = sub_matches
+        .ids()
+        .filter(|id| sub_matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .cloned()
+        .collect();
+    let overridden = overridden_ids(cmd, &explicit);
+
+    let mut config_args = Vec::new();
+    for (arg, value) in config.settings(cmd) {
+        if !overridden.contains(arg.get_id()) {
+            config_args.extend(setting_args(arg, &value)?);
+        }
+    }
+
+    // Settings are inserted right after the subcommand, before any options given with it
+    let index = subcommand_index(cmd, &args).map_or(args.len(), |i| i + 1);
+    let mut args = args;
+    args.splice(index..index, config_args);
+    Ok(args)
+}
+
+/// Position of the subcommand in the command line arguments, which may be given by its name,
+/// its flag or one of their aliases
+fn subcommand_index(cmd: &Command, args: &[OsString]) -> Option<usize> {
+    let mut names: Vec<String> = std::iter::once(cmd.get_name())
+        .chain(cmd.get_all_aliases())
+        .map(str::to_string)
+        .collect();
+    names.extend(
+        cmd.get_long_flag()
+            .into_iter()
+            .chain(cmd.get_all_long_flag_aliases())
+            .map(|flag| format!("--{flag}")),
+    );
+    names.extend(
+        cmd.get_short_flag()
+            .into_iter()
+            .chain(cmd.get_all_short_flag_aliases())
+            .map(|flag| format!("-{flag}")),
+    );
+
+    // The first argument is the program name
+    args.iter()
+        .skip(1)
+        .position(|arg| names.iter().any(|name| arg == name.as_str()))
+        .map(|i| i + 1)
+}
+
+fn setting_value(value: String) -> Value {
+    let is_number = value
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == '-' || c == '.');
+
+    match (value.parse::<i64>(), value.parse::<f64>()) {
+        (Ok(value), _) if is_number => Value::Integer(value),
+        (_, Ok(value)) if is_number => Value::Float(value),
+        _ => Value::String(value),
+    }
+}
+
+/// Effective options of a subcommand as a config file. Options that are not set are left out
+#[must_use]
+pub fn config_toml(cmd: &Command, matches: &ArgMatches) -> String {
+    let mut table = Table::new();
+
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        if EXCLUDED_IDS.contains(&id) {
+            continue;
+        }
+
+        let value = if is_flag(arg) {
+            if !matches.get_flag(id) {
+                continue;
+            }
+            Value::Boolean(true)
+        } else {
+            let Some(values) = matches.get_raw(id) else {
+                continue;
+            };
+            let mut values = values.map(|value| value.to_string_lossy().into_owned());
+
+            if is_list(arg) {
+                Value::Array(values.map(Value::String).collect())
+            } else {
+                setting_value(values.next().unwrap_or_default())
+            }
+        };
+
+        table.insert(long.to_string(), value);
+    }
+
+    let mut config = Table::new();
+    config.insert(cmd.get_name().to_string(), Value::Table(table));
+    config.to_string()
+}
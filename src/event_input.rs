@@ -0,0 +1,200 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, ErrorKind, Read},
+    ops::Range,
+};
+
+use crate::{
+    aedat_data::{get_events, CameraParameters, CameraType, Event},
+    aedat_header_tools::{find_header_end, parse_camera_type},
+    cli_configs::{CsvInputConfig, EventColumn, InputConfig, PolarityEncoding, TimeUnit},
+    compact::{is_compact, CompactReader},
+    compression::{decompress_input, is_stdio},
+};
+
+/// Events loaded from an input file, along with the camera that recorded them
+pub struct EventInput {
+    pub events: Vec<Event>,
+    pub cam: CameraParameters,
+    /// ASCII header of the AEDAT file the events came from
+    pub header: String,
+}
+
+/// AEDAT 2.0 header for events that were not read from an AEDAT file
+#[must_use]
+pub fn aedat_header(camera_type: &CameraType, source: &str) -> String {
+    // Names that the camera type is recognized by when the header is parsed
+    let hardware_interface = match camera_type {
+        CameraType::DVS128 => "DVS128",
+        CameraType::DAVIS240 => "DAVIS240",
+    };
+
+    format!(
+        "#!AER-DAT2.0\r\n\
+         # This is a raw AE data file created by aedat_reader from {source}\r\n\
+         # HardwareInterface: {hardware_interface}\r\n\
+         #End Of ASCII Header\r\n"
+    )
+}
+
+fn csv_error(line: usize, msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("Line {line}: {msg}"))
+}
+
+fn parse_csv_timestamp(value: &str, unit: &TimeUnit) -> Option<i32> {
+    let scale = match unit {
+        TimeUnit::Microseconds => return value.parse().ok(),
+        TimeUnit::Milliseconds => 1_000.0,
+        TimeUnit::Seconds => 1_000_000.0,
+    };
+
+    let timestamp = (value.parse::<f64>().ok()? * scale).round();
+    (f64::from(i32::MIN)..=f64::from(i32::MAX))
+        .contains(&timestamp)
+        .then_some(timestamp as i32)
+}
+
+fn parse_csv_polarity(value: &str, encoding: &PolarityEncoding) -> Option<bool> {
+    match (value, encoding) {
+        ("1", _) => Some(true),
+        ("-1", PolarityEncoding::Signed) | ("0", PolarityEncoding::Binary) => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse events from CSV text with the column layout of `config`. A first line that does not
+/// hold an event is treated as a header. Events are sorted by timestamp
+pub fn parse_csv_events(data: &[u8], config: &CsvInputConfig) -> io::Result<Vec<Event>> {
+    let cam = CameraParameters::new(config.camera_type.clone());
+    let text = std::str::from_utf8(data)
+        .map_err(|_| io::Error::new(ErrorKind::InvalidData, "CSV input is not valid UTF-8"))?;
+
+    let mut events = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut timestamp = None;
+        let mut x = None;
+        let mut y = None;
+        let mut polarity = None;
+
+        let mut fields = line.split(config.delimiter).map(str::trim);
+        for column in &config.columns {
+            let Some(field) = fields.next() else {
+                break;
+            };
+
+            match column {
+                Some(EventColumn::Timestamp) => {
+                    timestamp = parse_csv_timestamp(field, &config.time_unit);
+                }
+                Some(EventColumn::X) => x = field.parse::<i32>().ok(),
+                Some(EventColumn::Y) => y = field.parse::<i32>().ok(),
+                Some(EventColumn::Polarity) => {
+                    polarity = parse_csv_polarity(field, &config.polarity);
+                }
+                _ => (),
+            }
+        }
+
+        let (Some(timestamp), Some(x), Some(y), Some(polarity)) = (timestamp, x, y, polarity)
+        else {
+            if i == 0 {
+                continue;
+            }
+            return Err(csv_error(i + 1, "Could not parse event"));
+        };
+
+        // Convert to the 1-based coordinates of the crate
+        let base = i32::from(config.coord_base);
+        let (x, y) = (x + 1 - base, y + 1 - base);
+        if !(1..=i32::from(cam.camera_x)).contains(&x)
+            || !(1..=i32::from(cam.camera_y)).contains(&y)
+        {
+            return Err(csv_error(
+                i + 1,
+                "Event coordinates are outside of the sensor",
+            ));
+        }
+
+        events.push(Event::new(
+            x as u8,
+            y as u8,
+            polarity,
+            timestamp,
+            &cam.camera_type,
+        ));
+    }
+
+    events.sort_by_key(Event::get_timestamp);
+
+    Ok(events)
+}
+
+/// Parse the contents of an AEDAT or compact event file, which may be gzip or zstd compressed,
+/// keeping the events in `time_range` if given
+pub fn parse_input(data: Vec<u8>, time_range: Option<&Range<i32>>) -> io::Result<EventInput> {
+    let data = decompress_input(data)?;
+
+    if is_compact(&data) {
+        let mut reader = CompactReader::new(Cursor::new(data))?;
+        // Only the blocks that overlap the time range are decoded
+        let events = match time_range {
+            Some(range) => reader.read_time_range(range.start, range.end)?,
+            None => reader.read_events()?,
+        };
+        let header = reader.header().to_string();
+        let cam = CameraParameters::new(reader.camera().camera_type.clone());
+
+        return Ok(EventInput {
+            events,
+            cam,
+            header,
+        });
+    }
+
+    let cam = parse_camera_type(&data)?;
+    let header_end = find_header_end(&data)?;
+    let header = String::from_utf8_lossy(&data[..header_end as usize]).into_owned();
+    let mut events = get_events(header_end, &data)?;
+    if let Some(range) = time_range {
+        events.retain(|event| range.contains(&event.get_timestamp()));
+    }
+
+    Ok(EventInput {
+        events,
+        cam,
+        header,
+    })
+}
+
+/// Read an AEDAT, compact event or CSV file, or standard input if the filename is `-`
+pub fn read_input(config: &InputConfig) -> io::Result<EventInput> {
+    let mut data = Vec::new();
+    if is_stdio(&config.filename) {
+        io::stdin().lock().read_to_end(&mut data)?;
+    } else {
+        File::open(&config.filename)?.read_to_end(&mut data)?;
+    }
+
+    match &config.csv {
+        Some(csv) => {
+            let mut events = parse_csv_events(&decompress_input(data)?, csv)?;
+            if let Some(range) = &config.time_range {
+                events.retain(|event| range.contains(&event.get_timestamp()));
+            }
+            let header = aedat_header(&csv.camera_type, &config.filename.display().to_string());
+
+            Ok(EventInput {
+                events,
+                cam: CameraParameters::new(csv.camera_type.clone()),
+                header,
+            })
+        }
+        None => parse_input(data, config.time_range.as_ref()),
+    }
+}
@@ -1,99 +1,368 @@
 mod aedat_data;
 mod aedat_header_tools;
+mod batch;
 mod cli_configs;
+mod compact;
+mod compression;
+mod config_file;
+mod event_input;
+mod npy;
 mod tests;
+mod transform;
+mod undistort;
 
 mod aedat_conversions;
 
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{self, BufWriter, Write};
 use std::path::PathBuf;
 use std::process;
 use std::time::Instant;
 
+use clap::builder::RangedU64ValueParser;
 use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
 
-use crate::aedat_data::get_events;
-use crate::aedat_header_tools::{find_header_end, parse_camera_type};
+use crate::aedat_data::write_aedat;
+use crate::batch::{collect_inputs, is_batch, print_summary, run_batch};
 use crate::cli_configs::*;
+use crate::compact::write_compact;
+use crate::config_file::{apply_config_file, config_toml};
+use crate::event_input::{read_input, EventInput};
+use aedat_conversions::columnar::create_columnar;
 use aedat_conversions::csv::create_csv;
+#[cfg(feature = "hdf5")]
+use aedat_conversions::hdf5::create_hdf5;
+use aedat_conversions::npy_events::create_npy_events;
+use aedat_conversions::tensor::create_tensors;
+use aedat_conversions::time_surface::create_time_surfaces;
 use aedat_conversions::time_window_csv::*;
 use aedat_conversions::video::{create_event_based_video, create_time_based_video};
 
-fn csv_convert(args: &ArgMatches) {
-    let csv_config = CsvConfig::new(args).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments\n{err}");
-        process::exit(1);
-    });
+/// Read the events of the input file
+fn read_events(args: &ArgMatches, paths: &FilePaths) -> io::Result<EventInput> {
+    let input_config = InputConfig::new(args, paths)?;
 
-    // Read file
-    let aedat_filename = args.get_one::<PathBuf>("filename").unwrap();
+    read_input(&input_config)
+}
 
-    let mut f = File::open(aedat_filename).unwrap();
-    let mut aedat_file = Vec::new();
-    f.read_to_end(&mut aedat_file).unwrap();
+fn csv_convert(args: &ArgMatches, paths: &FilePaths) -> io::Result<()> {
+    let csv_config = CsvConfig::new(args, paths)?;
 
-    let cam = parse_camera_type(&aedat_file).unwrap();
-    let header_end = find_header_end(&aedat_file).unwrap();
-    let events = get_events(header_end, &aedat_file).unwrap();
+    // Read file
+    let EventInput { events, cam, .. } = read_events(args, paths)?;
 
     let now = Instant::now();
 
-    create_csv(events, &csv_config, &cam).unwrap();
+    match csv_config.format {
+        EventFormat::Csv => create_csv(events, &csv_config, &cam)?,
+        EventFormat::Parquet | EventFormat::ArrowIpc => {
+            create_columnar(events, &csv_config, &cam)?;
+        }
+        EventFormat::Npy | EventFormat::Npz => {
+            create_npy_events(events, &csv_config, &cam)?;
+        }
+    }
 
     let elapsed = now.elapsed();
     let sec = (elapsed.as_secs() as f64) + (f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0);
-    println!("Export time: {sec} seconds");
+    // Reported on stderr so that the CSV can be written to stdout
+    eprintln!("Export time: {sec} seconds");
+
+    Ok(())
 }
 
-fn vid_convert(args: &ArgMatches) {
-    let vid_config = VidConfig::new(args).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments\n{err}");
-        process::exit(1);
-    });
+fn vid_convert(args: &ArgMatches, paths: &FilePaths) -> io::Result<()> {
+    let vid_config = VidConfig::new(args, paths)?;
 
     // Read file
-    let aedat_filename = args.get_one::<PathBuf>("filename").unwrap();
-    let mut f = File::open(aedat_filename).unwrap();
-    let mut aedat_file = Vec::new();
-    f.read_to_end(&mut aedat_file).unwrap();
-
-    let cam = parse_camera_type(&aedat_file).unwrap();
-
-    let header_end = find_header_end(&aedat_file).unwrap();
-
-    let events = get_events(header_end, &aedat_file).unwrap();
+    let EventInput { events, cam, .. } = read_events(args, paths)?;
 
     if args.get_flag("timeBasedReconstruction") {
-        create_time_based_video(events, &vid_config, &cam).unwrap();
+        create_time_based_video(events, &vid_config, &cam)
     } else {
-        create_event_based_video(events, &vid_config, &cam).unwrap();
+        create_event_based_video(events, &vid_config, &cam)
     }
 }
 
-fn time_window_convert(args: &ArgMatches) {
-    let time_window_config = TimeWindowConfig::new(args).unwrap_or_else(|err| {
+fn time_window_convert(args: &ArgMatches, paths: &FilePaths) -> io::Result<()> {
+    let time_window_config = TimeWindowConfig::new(args, paths)?;
+
+    // Read file
+    let EventInput { events, cam, .. } = read_events(args, paths)?;
+
+    create_time_window_csv(events, &time_window_config, &cam)
+}
+
+fn time_surface_convert(args: &ArgMatches, paths: &FilePaths) -> io::Result<()> {
+    let time_surface_config = TimeSurfaceConfig::new(args, paths)?;
+
+    // Read file
+    let EventInput { events, cam, .. } = read_events(args, paths)?;
+
+    create_time_surfaces(events, &time_surface_config, &cam)
+}
+
+fn tensor_convert(args: &ArgMatches, paths: &FilePaths) -> io::Result<()> {
+    let tensor_config = TensorConfig::new(args, paths)?;
+
+    // Read file
+    let EventInput { events, cam, .. } = read_events(args, paths)?;
+
+    create_tensors(events, &tensor_config, &cam)
+}
+
+fn aedat_convert(args: &ArgMatches, paths: &FilePaths) -> io::Result<()> {
+    let aedat_config = AedatConfig::new(paths)?;
+
+    // Read file
+    let EventInput { events, header, .. } = read_events(args, paths)?;
+
+    let mut writer = BufWriter::new(File::create(&aedat_config.filename)?);
+    write_aedat(&mut writer, &events, &header)?;
+    writer.flush()
+}
+
+fn compact_convert(args: &ArgMatches, paths: &FilePaths) -> io::Result<()> {
+    let compact_config = CompactConfig::new(args, paths)?;
+
+    // Read file
+    let EventInput {
+        events,
+        cam,
+        header,
+    } = read_events(args, paths)?;
+
+    let mut writer = BufWriter::new(File::create(&compact_config.filename)?);
+    write_compact(&mut writer, &events, &header, &cam, &compact_config.options)?;
+    writer.flush()
+}
+
+#[cfg(feature = "hdf5")]
+fn hdf5_convert(args: &ArgMatches, paths: &FilePaths) -> io::Result<()> {
+    let hdf5_config = Hdf5Config::new(args, paths)?;
+
+    // Read file
+    let EventInput {
+        events,
+        cam,
+        header,
+    } = read_events(args, paths)?;
+
+    create_hdf5(events, &header, &hdf5_config, &cam)
+}
+
+/// Convert the input files of a subcommand. A single file is converted directly, exiting on
+/// the first error. Several files, directories and globs are converted in parallel, reporting
+/// the outcome of every file
+fn run_conversion(args: &ArgMatches, convert: fn(&ArgMatches, &FilePaths) -> io::Result<()>) {
+    let filenames: Vec<PathBuf> = args
+        .get_many::<PathBuf>("filename")
+        .unwrap()
+        .cloned()
+        .collect();
+
+    if !is_batch(&filenames) {
+        let paths = FilePaths::new(args);
+        if let Err(err) = convert(args, &paths) {
+            // The output was piped into a program that stopped reading, e.g. head
+            if err.kind() == io::ErrorKind::BrokenPipe {
+                return;
+            }
+            eprintln!("Problem converting {}\n{err}", paths.input.display());
+            process::exit(1);
+        }
+        return;
+    }
+
+    let exit_on_error = |err: io::Error| -> ! {
         eprintln!("Problem parsing arguments\n{err}");
         process::exit(1);
-    });
+    };
 
-    // Read file
-    let aedat_filename = args.get_one::<PathBuf>("filename").unwrap();
-    let mut f = File::open(aedat_filename).unwrap();
-    let mut aedat_file = Vec::new();
-    f.read_to_end(&mut aedat_file).unwrap();
+    let inputs = collect_inputs(&filenames, args.contains_id("sensorSize"))
+        .unwrap_or_else(|err| exit_on_error(err));
+    let output_root = args.get_one::<PathBuf>("output").map(PathBuf::as_path);
+    let jobs = args.get_one::<usize>("jobs").unwrap().to_owned();
+
+    let results = run_batch(&inputs, output_root, jobs, |paths| convert(args, paths))
+        .unwrap_or_else(|err| exit_on_error(err));
+
+    if print_summary(&results) > 0 {
+        process::exit(1);
+    }
+}
 
-    let cam = parse_camera_type(&aedat_file).unwrap();
+fn transform_args() -> [Arg; 4] {
+    [
+        Arg::new("flipX")
+            .long("flip_x")
+            .action(ArgAction::SetTrue)
+            .help("Mirror events horizontally"),
+        Arg::new("flipY")
+            .long("flip_y")
+            .action(ArgAction::SetTrue)
+            .help("Mirror events vertically"),
+        Arg::new("transpose")
+            .long("transpose")
+            .action(ArgAction::SetTrue)
+            .help("Swap the x and y axes of events. Applied after any flips"),
+        Arg::new("rotate")
+            .long("rotate")
+            .value_parser(["90", "180", "270"])
+            .action(ArgAction::Set)
+            .help(
+                "Rotate events clockwise by the given number of degrees. Applied after any flips and transpose",
+            ),
+    ]
+}
 
-    let header_end = find_header_end(&aedat_file).unwrap();
+#[cfg(feature = "hdf5")]
+fn hdf5_command() -> Command {
+    Command::new("hdf5")
+        .long_flag("hdf5")
+        .about(
+            "Export AEDAT to HDF5 with the events/t, events/x, events/y, events/p dataset layout",
+        )
+        .arg(
+            Arg::new("filename")
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .required_unless_present("printConfig")
+                .help("AEDAT, compact event or CSV files, directories or glob patterns to be processed, or - to read from stdin"),
+        )
+        .arg(
+            Arg::new("compression")
+                .long("compression")
+                .short('c')
+                .value_parser(clap::value_parser!(u8).range(0..=9))
+                .default_value("4")
+                .action(ArgAction::Set)
+                .help("Gzip compression level of the datasets. 0 disables compression"),
+        )
+        .arg(
+            Arg::new("chunkSize")
+                .long("chunk_size")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("16384")
+                .action(ArgAction::Set)
+                .help("Number of elements in each chunk of the datasets"),
+        )
+        .arg(output_arg())
+        .arg(jobs_arg())
+        .args(config_args())
+        .args(input_args())
+        .args(transform_args())
+}
 
-    let events = get_events(header_end, &aedat_file).unwrap();
+fn input_args() -> [Arg; 8] {
+    [
+        Arg::new("sensorSize")
+            .long("sensor_size")
+            .value_parser(["128x128", "240x180"])
+            .action(ArgAction::Set)
+            .help("Read the input as a CSV of events recorded by a sensor of this size (WIDTHxHEIGHT)"),
+        Arg::new("csvColumns")
+            .long("csv_columns")
+            .requires("sensorSize")
+            .value_delimiter(',')
+            .value_parser(["t", "x", "y", "p", "_"])
+            .default_value("p,x,y,t")
+            .action(ArgAction::Append)
+            .help("Comma separated meaning of each input CSV column. Use _ to skip a column"),
+        Arg::new("csvDelimiter")
+            .long("csv_delimiter")
+            .requires("sensorSize")
+            .default_value(",")
+            .action(ArgAction::Set)
+            .help("Character separating the input CSV columns. Use tab or space for whitespace"),
+        Arg::new("csvTimeUnit")
+            .long("csv_time_unit")
+            .requires("sensorSize")
+            .value_parser(["us", "ms", "s"])
+            .default_value("us")
+            .action(ArgAction::Set)
+            .help("Unit of the input CSV timestamps"),
+        Arg::new("csvPolarity")
+            .long("csv_polarity")
+            .requires("sensorSize")
+            .value_parser(["signed", "binary"])
+            .default_value("signed")
+            .action(ArgAction::Set)
+            .help("Input CSV polarity of ON and OFF events: 1 and -1 (signed) or 1 and 0 (binary)"),
+        Arg::new("csvCoordBase")
+            .long("csv_coord_base")
+            .requires("sensorSize")
+            .value_parser(clap::value_parser!(u8).range(0..=1))
+            .default_value("1")
+            .action(ArgAction::Set)
+            .help("Coordinate of the first pixel in the input CSV"),
+        Arg::new("startTime")
+            .long("start")
+            .value_parser(clap::value_parser!(i32))
+            .allow_negative_numbers(true)
+            .action(ArgAction::Set)
+            .help("Only convert events with timestamps from this time on (microseconds)"),
+        Arg::new("endTime")
+            .long("end")
+            .value_parser(clap::value_parser!(i32))
+            .allow_negative_numbers(true)
+            .action(ArgAction::Set)
+            .help("Only convert events with timestamps before this time (microseconds)"),
+    ]
+}
 
-    create_time_window_csv(events, &time_window_config, &cam).unwrap();
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .long("output")
+        .value_parser(clap::value_parser!(PathBuf))
+        .action(ArgAction::Set)
+        .help("Output path to use instead of one derived from the input filename. Use - to write CSV output to stdout")
 }
 
-fn main() {
-    let matches = Command::new("aedat_reader")
+fn compress_arg() -> Arg {
+    Arg::new("compress")
+        .long("compress")
+        .value_parser(["gzip", "zstd"])
+        .action(ArgAction::Set)
+        .help("Compress the CSV, appending .gz or .zst to its filename")
+}
+
+fn jobs_arg() -> Arg {
+    Arg::new("jobs")
+        .long("jobs")
+        .short('j')
+        .value_parser(clap::value_parser!(usize))
+        .default_value("0")
+        .action(ArgAction::Set)
+        .help("Number of files converted in parallel when converting several files. 0 uses one per core")
+}
+
+fn config_args() -> [Arg; 2] {
+    [
+        Arg::new("config")
+            .long("config")
+            .value_parser(clap::value_parser!(PathBuf))
+            .action(ArgAction::Set)
+            .help("TOML file with the options of the conversion. Options on the command line take precedence"),
+        Arg::new("printConfig")
+            .long("print_config")
+            .alias("print-config")
+            .action(ArgAction::SetTrue)
+            .help("Print the effective options as a TOML config file instead of converting"),
+    ]
+}
+
+fn calibration_arg() -> Arg {
+    Arg::new("calibration")
+        .long("calibration")
+        .value_parser(clap::value_parser!(PathBuf))
+        .action(ArgAction::Set)
+        .help("OpenCV camera calibration file (YAML or JSON) used to undistort event coordinates")
+}
+
+fn cli() -> Command {
+    let command = Command::new("aedat_reader")
         .about("Program for converting AEDAT files to CSV or video.")
         .subcommand_required(true)
         .arg_required_else_help(true)
@@ -104,9 +373,20 @@ fn main() {
                 .arg(
                     Arg::new("filename")
                         .value_parser(clap::value_parser!(PathBuf))
+                        .num_args(1..)
+                        .action(ArgAction::Append)
+                        .required_unless_present("printConfig")
+                        .help("AEDAT, compact event or CSV files, directories or glob patterns to be processed, or - to read from stdin"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .value_parser(["csv", "parquet", "arrow", "npy", "npz"])
+                        .default_value("csv")
                         .action(ArgAction::Set)
-                        .required(true)
-                        .help("Path to the AEDAT file to be processed"),
+                        .help("Write a CSV file, typed columns to a Parquet or Arrow IPC file, \
+                               or a NumPy structured array to a .npy file or compressed .npz archive")
                 )
                 .group(
                     ArgGroup::new("csv_spatial")
@@ -159,18 +439,88 @@ fn main() {
                         .long("offset_time")
                         .action(ArgAction::SetTrue)
                         .help("Start timestamps in the exported csv at 0")
-                ),
+                )
+                .arg(calibration_arg())
+                .arg(
+                    Arg::new("roundUndistorted")
+                        .long("round_undistorted")
+                        .requires("calibration")
+                        .action(ArgAction::SetTrue)
+                        .help("Round the undistorted coordinates to the nearest pixel")
+                )
+                .arg(
+                    Arg::new("pixelOrder")
+                        .long("pixel_order")
+                        .value_parser(["row_major", "column_major"])
+                        .default_value("row_major")
+                        .action(ArgAction::Set)
+                        .help("Number pixels along rows (y * width + x) or along columns (x * height + y)")
+                )
+                .arg(
+                    Arg::new("pixelBase")
+                        .long("pixel_base")
+                        .value_parser(clap::value_parser!(u32).range(0..=1))
+                        .default_value("0")
+                        .action(ArgAction::Set)
+                        .help("Number of the first pixel")
+                )
+                .arg(
+                    Arg::new("delimiter")
+                        .long("delimiter")
+                        .short('d')
+                        .default_value(",")
+                        .action(ArgAction::Set)
+                        .help("Character separating the CSV columns. Use tab or space for whitespace")
+                )
+                .arg(
+                    Arg::new("columnOrder")
+                        .long("column_order")
+                        .value_delimiter(',')
+                        .value_parser(["t", "x", "y", "p", "pixel_number", "x_undist", "y_undist"])
+                        .action(ArgAction::Append)
+                        .help("Comma separated list of CSV columns to write first, e.g. t,x,y,p. \
+                               Columns that are not listed follow in their default order")
+                )
+                .arg(
+                    Arg::new("timeUnit")
+                        .long("time_unit")
+                        .value_parser(["us", "ms", "s"])
+                        .default_value("us")
+                        .action(ArgAction::Set)
+                        .help("Unit of the CSV timestamps. Milliseconds and seconds are written as decimals")
+                )
+                .arg(
+                    Arg::new("polarityEncoding")
+                        .long("polarity_encoding")
+                        .value_parser(["signed", "binary"])
+                        .default_value("signed")
+                        .action(ArgAction::Set)
+                        .help("Write the CSV polarity of ON and OFF events as 1 and -1 (signed) or 1 and 0 (binary)")
+                )
+                .arg(
+                    Arg::new("noHeader")
+                        .long("no_header")
+                        .action(ArgAction::SetTrue)
+                        .help("Do not write a header row to the CSV")
+                )
+                .arg(compress_arg())
+                .arg(output_arg())
+                .arg(jobs_arg())
+                .args(config_args())
+                .args(input_args())
+                .args(transform_args()),
         )
         .subcommand(
             Command::new("vid")
                 .long_flag("vid")
-                .about("Export AEDAT to AVI video")
+                .about("Export AEDAT to video")
                 .arg(
                     Arg::new("filename")
                         .value_parser(clap::value_parser!(PathBuf))
-                        .action(ArgAction::Set)
-                        .required(true)
-                        .help("Path to the AEDAT file to be processed"),
+                        .num_args(1..)
+                        .action(ArgAction::Append)
+                        .required_unless_present("printConfig")
+                        .help("AEDAT, compact event or CSV files, directories or glob patterns to be processed, or - to read from stdin"),
                 )
                 .group(
                     ArgGroup::new("reconstructionMethod")
@@ -193,11 +543,19 @@ fn main() {
                     Arg::new("windowSize")
                         .long("window_size")
                         .short('w')
-                        .value_parser(clap::value_parser!(usize))
+                        .value_parser(RangedU64ValueParser::<usize>::new().range(1..))
                         .action(ArgAction::Set)
                         .required(true)
                         .help("The duration of each frame. Microseconds for time based reconstruction; number of events for event based reconstruction"),
                 )
+                .arg(
+                    Arg::new("stride")
+                        .long("stride")
+                        .requires("timeBasedReconstruction")
+                        .value_parser(RangedU64ValueParser::<usize>::new().range(1..))
+                        .action(ArgAction::Set)
+                        .help("Start a frame every stride microseconds, so that frames overlap when the stride is smaller than the window size. Requires --time_based"),
+                )
                 .arg(
                     Arg::new("maxFrames")
                         .long("max_frames")
@@ -233,25 +591,169 @@ fn main() {
                         .short('o')
                         .action(ArgAction::SetTrue)
                         .help("Do not compile the reconstructed frames into a video"),
-                ),
+                )
+                .arg(calibration_arg())
+                .arg(
+                    Arg::new("renderMode")
+                        .long("render")
+                        .value_parser(["last_event", "decay", "time_surface", "counts"])
+                        .default_value("last_event")
+                        .action(ArgAction::Set)
+                        .help("How events are drawn. last_event colors each pixel by its most recent event; \
+                               decay accumulates events into intensities that fade over time, leaving motion trails; \
+                               time_surface shows the exponentially decayed time since the latest event of each pixel; \
+                               counts colors each pixel by its number of events using the selected colormap"),
+                )
+                .arg(
+                    Arg::new("decayTime")
+                        .long("decay_time")
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                        .default_value("10000")
+                        .action(ArgAction::Set)
+                        .help("Time constant of the exponential decay in microseconds. Used by the decay and time_surface render modes"),
+                )
+                .arg(
+                    Arg::new("palette")
+                        .long("palette")
+                        .value_parser(["green_red", "red_blue", "white_black"])
+                        .default_value("green_red")
+                        .action(ArgAction::Set)
+                        .help("Colors of ON and OFF events and the background: green/red on black, red/blue on white or white/black on grey"),
+                )
+                .arg(
+                    Arg::new("onColor")
+                        .long("on_color")
+                        .action(ArgAction::Set)
+                        .help("Hex color (#RRGGBB) of ON events. Overrides the palette"),
+                )
+                .arg(
+                    Arg::new("offColor")
+                        .long("off_color")
+                        .action(ArgAction::Set)
+                        .help("Hex color (#RRGGBB) of OFF events. Overrides the palette"),
+                )
+                .arg(
+                    Arg::new("backgroundColor")
+                        .long("background_color")
+                        .action(ArgAction::Set)
+                        .help("Hex color (#RRGGBB) of the background. Overrides the palette"),
+                )
+                .arg(
+                    Arg::new("colormap")
+                        .long("colormap")
+                        .value_parser(["viridis", "inferno", "grey"])
+                        .default_value("viridis")
+                        .action(ArgAction::Set)
+                        .help("Colormap used by the counts render mode for unsigned counts"),
+                )
+                .arg(
+                    Arg::new("countMode")
+                        .long("count_mode")
+                        .value_parser(["unsigned", "signed"])
+                        .default_value("unsigned")
+                        .action(ArgAction::Set)
+                        .help("Count all events, or add ON events and subtract OFF events. Signed counts are drawn with the palette colors"),
+                )
+                .arg(
+                    Arg::new("countClip")
+                        .long("count_clip")
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                        .action(ArgAction::Set)
+                        .help("Clip counts to this value when mapping them to intensities. By default counts are normalized by the busiest pixel of each frame"),
+                )
+                .arg(
+                    Arg::new("saveCounts")
+                        .long("save_counts")
+                        .action(ArgAction::SetTrue)
+                        .help("Save the raw per-pixel counts of every frame to a .npz archive. Requires --render counts"),
+                )
+                .arg(
+                    Arg::new("overlay")
+                        .long("overlay")
+                        .action(ArgAction::SetTrue)
+                        .help("Draw the frame index, window start and end time, event count and event rate on each frame"),
+                )
+                .arg(
+                    Arg::new("scaleBar")
+                        .long("scale_bar")
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                        .action(ArgAction::Set)
+                        .help("Draw a scale bar of this many sensor pixels in the bottom left corner of each frame"),
+                )
+                .arg(
+                    Arg::new("threads")
+                        .long("threads")
+                        .value_parser(clap::value_parser!(usize))
+                        .action(ArgAction::Set)
+                        .help("Number of threads used to render and encode frames. Defaults to one per core, shared with the --jobs threads when converting several files"),
+                )
+                .arg(
+                    Arg::new("codec")
+                        .long("codec")
+                        .default_value("MJPG")
+                        .action(ArgAction::Set)
+                        .help("FourCC of the video codec, e.g. MJPG, XVID, FFV1 (lossless) or mp4v"),
+                )
+                .arg(
+                    Arg::new("container")
+                        .long("container")
+                        .default_value("avi")
+                        .action(ArgAction::Set)
+                        .help("File extension of the video container, e.g. avi, mkv or mp4"),
+                )
+                .arg(
+                    Arg::new("fps")
+                        .long("fps")
+                        .default_value("30")
+                        .action(ArgAction::Set)
+                        .help("Frame rate of the video. Use real_time with time based reconstruction to play back at the recorded speed"),
+                )
+                .arg(
+                    Arg::new("scale")
+                        .long("scale")
+                        .value_parser(clap::value_parser!(u32).range(1..=32))
+                        .default_value("1")
+                        .action(ArgAction::Set)
+                        .help("Integer factor by which frames are upscaled using nearest neighbour filtering"),
+                )
+                .arg(output_arg())
+                .arg(jobs_arg())
+                .args(config_args())
+                .args(input_args())
+                .args(transform_args()),
         ).subcommand(Command::new("time_windows")
             .long_flag("time_windows")
             .about("Export AEDAT to a series of time windows")
             .arg(
                 Arg::new("filename")
                     .value_parser(clap::value_parser!(PathBuf))
-                    .action(ArgAction::Set)
-                    .required(true)
-                    .help("Path to the AEDAT file to be processed"),
+                    .num_args(1..)
+                    .action(ArgAction::Append)
+                    .required_unless_present("printConfig")
+                    .help("AEDAT, compact event or CSV files, directories or glob patterns to be processed, or - to read from stdin"),
             )
             .arg(
                 Arg::new("windowSize")
                     .long("window_size")
                     .short('w')
-                    .value_parser(clap::value_parser!(u32))
+                    .value_parser(clap::value_parser!(u32).range(1..))
                     .action(ArgAction::Set)
                     .required(true)
-                    .help("The duration of each window in microseconds"),
+                    .help("The duration of each window in microseconds, or its number of events with --event_based"),
+            )
+            .arg(
+                Arg::new("eventBasedReconstruction")
+                    .long("event_based")
+                    .action(ArgAction::SetTrue)
+                    .help("Slice events into windows of a fixed number of events. Each row also contains the start and end timestamps and duration of its window"),
+            )
+            .arg(
+                Arg::new("stride")
+                    .long("stride")
+                    .conflicts_with("eventBasedReconstruction")
+                    .value_parser(clap::value_parser!(u32).range(1..))
+                    .action(ArgAction::Set)
+                    .help("Start a window every stride microseconds, so that windows overlap when the stride is smaller than the window size"),
             )
             .arg(
                 Arg::new("maxWindows")
@@ -268,6 +770,26 @@ fn main() {
                     .action(ArgAction::SetTrue)
                     .help("Include a column containing the sum of the ON and OFF events in a given time window"),
             )
+            .arg(
+                Arg::new("columns")
+                    .long("columns")
+                    .value_delimiter(',')
+                    .value_parser([
+                        "start",
+                        "end",
+                        "duration",
+                        "both",
+                        "active_pixels",
+                        "event_rate",
+                        "polarity_ratio",
+                        "centroid_x",
+                        "centroid_y",
+                        "spread",
+                        "mean_interval",
+                    ])
+                    .action(ArgAction::Append)
+                    .help("Comma separated list of additional statistics columns, appended after the On and Off columns"),
+            )
             .arg(
                 Arg::new("includePgm")
                     .long("include_pgm")
@@ -277,7 +799,8 @@ fn main() {
                     .action(ArgAction::SetTrue)
                     .help("Include a downscaled PGM image in each row. \
                            Note that these PGM images will contain dashes in place of newline characters. \
-                           The dashes will need to be converted to newlines before the images can be opened"),
+                           The dashes will need to be converted to newlines before the images can be opened. \
+                           Use --pgm_format to write the images to files instead"),
             )
             .arg(
                 Arg::new("pgmThreshold")
@@ -296,14 +819,263 @@ fn main() {
                     .value_parser(clap::value_parser!(usize))
                     .action(ArgAction::Set)
                     .help("The factor at which the downscaled image is scaled by"),
-            ),
+            )
+            .arg(
+                Arg::new("pgmFormat")
+                    .long("pgm_format")
+                    .requires("includePgm")
+                    .value_parser(["string", "pgm", "png"])
+                    .default_value("string")
+                    .action(ArgAction::Set)
+                    .help("Embed each downscaled image in the CSV as a dash separated string, \
+                           or write it to a binary PGM or PNG file that the CSV references"),
+            )
+            .arg(
+                Arg::new("pgmCounts")
+                    .long("pgm_counts")
+                    .requires("includePgm")
+                    .action(ArgAction::SetTrue)
+                    .help("Use the number of events of each downscaled pixel as its grey level instead of a black and white image. \
+                           Pixels below the threshold are black"),
+            )
+            .arg(compress_arg())
+            .arg(output_arg())
+            .arg(jobs_arg())
+            .args(config_args())
+            .args(input_args())
+            .args(transform_args()),
+        )
+        .subcommand(
+            Command::new("time_surface")
+                .long_flag("time_surface")
+                .about("Export AEDAT to time surfaces sampled at a fixed interval")
+                .arg(
+                    Arg::new("filename")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .num_args(1..)
+                        .action(ArgAction::Append)
+                        .required_unless_present("printConfig")
+                        .help("AEDAT, compact event or CSV files, directories or glob patterns to be processed, or - to read from stdin"),
+                )
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .short('i')
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                        .action(ArgAction::Set)
+                        .required(true)
+                        .help("The time between sampled surfaces in microseconds"),
+                )
+                .arg(
+                    Arg::new("decayTime")
+                        .long("decay_time")
+                        .short('d')
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                        .default_value("10000")
+                        .action(ArgAction::Set)
+                        .help("Time constant of the exponential decay in microseconds"),
+                )
+                .arg(
+                    Arg::new("maxSurfaces")
+                        .long("max_surfaces")
+                        .short('m')
+                        .value_parser(clap::value_parser!(usize))
+                        .action(ArgAction::Set)
+                        .help("The maximum number of surfaces to be exported"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .value_parser(["npy", "png"])
+                        .default_value("npy")
+                        .action(ArgAction::Set)
+                        .help("Export each surface as a 2xHxW float32 NumPy array or as a pair of 16-bit PNGs"),
+                )
+                .arg(output_arg())
+                .arg(jobs_arg())
+                .args(config_args())
+                .args(input_args())
+                .args(transform_args()),
+        )
+        .subcommand(
+            Command::new("tensor")
+                .long_flag("tensor")
+                .about("Export AEDAT to voxel grids or event histograms for deep learning")
+                .arg(
+                    Arg::new("filename")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .num_args(1..)
+                        .action(ArgAction::Append)
+                        .required_unless_present("printConfig")
+                        .help("AEDAT, compact event or CSV files, directories or glob patterns to be processed, or - to read from stdin"),
+                )
+                .group(
+                    ArgGroup::new("windowMethod")
+                        .args(["timeBasedReconstruction", "eventBasedReconstruction"])
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("timeBasedReconstruction")
+                        .long("time_based")
+                        .action(ArgAction::SetTrue)
+                        .help("Slice events into windows of a fixed duration"),
+                )
+                .arg(
+                    Arg::new("eventBasedReconstruction")
+                        .long("event_based")
+                        .action(ArgAction::SetTrue)
+                        .help("Slice events into windows of a fixed number of events"),
+                )
+                .arg(
+                    Arg::new("windowSize")
+                        .long("window_size")
+                        .short('w')
+                        .value_parser(clap::value_parser!(usize))
+                        .action(ArgAction::Set)
+                        .required(true)
+                        .help("The size of each window. Microseconds for time based windows; number of events for event based windows"),
+                )
+                .arg(
+                    Arg::new("maxWindows")
+                        .long("max_windows")
+                        .short('m')
+                        .value_parser(clap::value_parser!(usize))
+                        .action(ArgAction::Set)
+                        .help("The maximum number of windows to be exported"),
+                )
+                .arg(
+                    Arg::new("representation")
+                        .long("representation")
+                        .short('r')
+                        .value_parser(["voxel_grid", "histogram"])
+                        .default_value("voxel_grid")
+                        .action(ArgAction::Set)
+                        .help("Export each window as a BxHxW voxel grid or a 2xHxW ON/OFF histogram"),
+                )
+                .arg(
+                    Arg::new("bins")
+                        .long("bins")
+                        .short('b')
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("5")
+                        .action(ArgAction::Set)
+                        .help("The number of temporal bins in each voxel grid"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .value_parser(["npy", "npz"])
+                        .default_value("npz")
+                        .action(ArgAction::Set)
+                        .help("Write a single .npz archive, or a directory of .npy files with an index.csv of window timestamps"),
+                )
+                .arg(
+                    Arg::new("compressNpz")
+                        .long("compress_npz")
+                        .short('c')
+                        .action(ArgAction::SetTrue)
+                        .help("Compress the .npz archive"),
+                )
+                .arg(output_arg())
+                .arg(jobs_arg())
+                .args(config_args())
+                .args(input_args())
+                .args(transform_args()),
+        )
+        .subcommand(
+            Command::new("compact")
+                .long_flag("compact")
+                .about("Convert AEDAT to the compact binary event format, which every subcommand accepts as input")
+                .arg(
+                    Arg::new("filename")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .num_args(1..)
+                        .action(ArgAction::Append)
+                        .required_unless_present("printConfig")
+                        .help("AEDAT, compact event or CSV files, directories or glob patterns to be processed, or - to read from stdin"),
+                )
+                .arg(
+                    Arg::new("blockSize")
+                        .long("block_size")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("65536")
+                        .action(ArgAction::Set)
+                        .help("Number of events in each block. Smaller blocks make time range queries cheaper"),
+                )
+                .arg(
+                    Arg::new("compression")
+                        .long("compression")
+                        .short('c')
+                        .value_parser(clap::value_parser!(u8).range(0..=22))
+                        .default_value("3")
+                        .action(ArgAction::Set)
+                        .help("Zstd compression level of each block. 0 stores the blocks uncompressed"),
+                )
+                .arg(output_arg())
+                .arg(jobs_arg())
+                .args(config_args())
+                .args(input_args()),
         )
-        .get_matches();
+        .subcommand(
+            Command::new("aedat")
+                .long_flag("aedat")
+                .about("Convert events to an AEDAT 2.0 file")
+                .arg(
+                    Arg::new("filename")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .num_args(1..)
+                        .action(ArgAction::Append)
+                        .required_unless_present("printConfig")
+                        .help("CSV or compact event files, directories or glob patterns to be processed, or - to read from stdin"),
+                )
+                .arg(output_arg())
+                .arg(jobs_arg())
+                .args(config_args())
+                .args(input_args()),
+        );
+
+    // HDF5 export requires the system HDF5 library and is only built with the hdf5 feature
+    #[cfg(feature = "hdf5")]
+    let command = command.subcommand(hdf5_command());
+
+    command
+}
+
+fn main() {
+    let command = cli();
+
+    let args = apply_config_file(&command, std::env::args_os().collect()).unwrap_or_else(|err| {
+        eprintln!("Problem reading config file\n{err}");
+        process::exit(1);
+    });
+    let matches = command.clone().get_matches_from(args);
+
+    if let Some((name, sub_matches)) = matches.subcommand() {
+        if sub_matches.get_flag("printConfig") {
+            print!(
+                "{}",
+                config_toml(command.find_subcommand(name).unwrap(), sub_matches)
+            );
+            return;
+        }
+    }
 
     match matches.subcommand() {
-        Some(("csv", csv_matches)) => csv_convert(csv_matches),
-        Some(("vid", vid_matches)) => vid_convert(vid_matches),
-        Some(("time_windows", time_windows_matches)) => time_window_convert(time_windows_matches),
+        Some(("csv", csv_matches)) => run_conversion(csv_matches, csv_convert),
+        Some(("vid", vid_matches)) => run_conversion(vid_matches, vid_convert),
+        Some(("time_windows", time_windows_matches)) => {
+            run_conversion(time_windows_matches, time_window_convert)
+        }
+        Some(("time_surface", time_surface_matches)) => {
+            run_conversion(time_surface_matches, time_surface_convert)
+        }
+        Some(("tensor", tensor_matches)) => run_conversion(tensor_matches, tensor_convert),
+        Some(("compact", compact_matches)) => run_conversion(compact_matches, compact_convert),
+        Some(("aedat", aedat_matches)) => run_conversion(aedat_matches, aedat_convert),
+        #[cfg(feature = "hdf5")]
+        Some(("hdf5", hdf5_matches)) => run_conversion(hdf5_matches, hdf5_convert),
         _ => unreachable!(), // If all subcommands are defined above, anything else is unreachable
     }
 
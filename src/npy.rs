@@ -0,0 +1,151 @@
+use std::io::{self, Seek, Write};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Element types that can be written to a NumPy array
+pub trait NpyElement: Copy {
+    /// NumPy type descriptor, e.g. `<f4`
+    const DESCR: &'static str;
+
+    fn write_le(self, buf: &mut Vec<u8>);
+}
+
+impl NpyElement for f32 {
+    const DESCR: &'static str = "<f4";
+
+    fn write_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for i32 {
+    const DESCR: &'static str = "<i4";
+
+    fn write_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for i64 {
+    const DESCR: &'static str = "<i8";
+
+    fn write_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for u16 {
+    const DESCR: &'static str = "<u2";
+
+    fn write_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// Write the header of a version 1.0 `.npy` file.
+/// `descr` may be a plain type descriptor or a structured dtype list such as `[('t', '<i8')]`
+pub fn write_npy_header<W: Write>(writer: &mut W, descr: &str, shape: &[usize]) -> io::Result<()> {
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+
+    // Quote plain descriptors; structured dtypes are written as is
+    let descr = if descr.starts_with('[') {
+        descr.to_owned()
+    } else {
+        format!("'{descr}'")
+    };
+
+    let mut header = format!("{{'descr': {descr}, 'fortran_order': False, 'shape': {shape}, }}");
+
+    // Magic string (6), version (2) and header length (2) precede the header,
+    // which is padded with spaces and terminated by a newline to a multiple of 64 bytes
+    let unpadded_len = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded_len % 64) % 64));
+    header.push('\n');
+
+    let header_len = u16::try_from(header.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "NumPy header is too large"))?;
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&header_len.to_le_bytes())?;
+    writer.write_all(header.as_bytes())
+}
+
+/// Write a C-ordered array with the given shape as a `.npy` file
+pub fn write_npy<W: Write, T: NpyElement>(
+    writer: &mut W,
+    shape: &[usize],
+    data: &[T],
+) -> io::Result<()> {
+    if shape.iter().product::<usize>() != data.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Array shape does not match the number of elements",
+        ));
+    }
+
+    write_npy_header(writer, T::DESCR, shape)?;
+
+    let mut buf = Vec::with_capacity(std::mem::size_of_val(data));
+    for &value in data {
+        value.write_le(&mut buf);
+    }
+
+    writer.write_all(&buf)
+}
+
+/// Writer for `.npz` archives: a zip file containing one `.npy` file per array
+pub struct NpzWriter<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    options: SimpleFileOptions,
+}
+
+impl<W: Write + Seek> NpzWriter<W> {
+    /// Create an archive, compressing its arrays if requested (like `numpy.savez_compressed`)
+    pub fn new(writer: W, compressed: bool) -> Self {
+        let method = if compressed {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+
+        NpzWriter {
+            zip: ZipWriter::new(writer),
+            options: SimpleFileOptions::default()
+                .compression_method(method)
+                .large_file(true),
+        }
+    }
+
+    /// Start an array that will be loaded under `name`. The caller writes the `.npy`
+    /// header and data, which allows streaming arrays such as structured arrays
+    pub fn start_array(&mut self, name: &str) -> io::Result<&mut impl Write> {
+        self.zip
+            .start_file(format!("{name}.npy"), self.options)
+            .map_err(io::Error::other)?;
+        Ok(&mut self.zip)
+    }
+
+    /// Add an array that will be loaded under `name`
+    pub fn add_array<T: NpyElement>(
+        &mut self,
+        name: &str,
+        shape: &[usize],
+        data: &[T],
+    ) -> io::Result<()> {
+        write_npy(self.start_array(name)?, shape, data)
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        self.zip.finish().map_err(io::Error::other)
+    }
+}
@@ -1,9 +1,43 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
+    use std::path::PathBuf;
+
+    use arrow_array::{Array, Int64Array, Int8Array, UInt16Array};
+    use arrow_ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
     use crate::{
-        aedat_conversions::time_window_csv::Downres,
-        aedat_data::{CameraType, Event},
+        aedat_conversions::{
+            columnar::{create_columnar, event_schema},
+            csv::{create_csv, write_event_line},
+            npy_events::write_events_npy,
+            rendering::{
+                parse_hex_color, Colormap, CountFrame, CountNormalization, DecayCanvas, FrameInfo,
+                FrameRenderer, Palette,
+            },
+            tensor::{create_tensors, histogram, voxel_grid},
+            time_surface::TimeSurface,
+            time_window_csv::{create_time_window_csv, Downres, WindowStats},
+            video::{event_based_frames, time_based_frames},
+            windows::{millisecond_index, sliding_windows, split_windows, Window},
+        },
+        aedat_data::{CameraParameters, CameraType, Event},
         aedat_header_tools::{find_header_end, parse_camera_type},
+        batch::{collect_inputs, run_batch},
+        cli_configs::{
+            output_filename, text_output, CoordMode, CsvConfig, CsvFormatting, CsvInputConfig,
+            EventColumn, EventFormat, FilePaths, PgmOutput, PixelNumbering, PixelOrder,
+            PolarityEncoding, TensorConfig, TimeUnit, TimeWindowConfig, VidConfig, WindowColumn,
+            WindowMode,
+        },
+        compact::{write_compact, CompactOptions, CompactReader},
+        compression::{decompress_input, strip_compression_extension, OutputCompression},
+        config_file::{apply_config_file, config_toml},
+        event_input::{aedat_header, parse_csv_events},
+        npy::write_npy,
+        transform::{Rotation, Transform},
+        undistort::Undistortion,
     };
 
     #[test]
@@ -15,7 +49,7 @@ mod tests {
 
         // Get event polarity
         let polarity = test_event_struct.get_polarity(&CameraType::DVS128);
-        assert_eq!(polarity, true);
+        assert!(polarity);
 
         // Get timestamp
         let timestamp = test_event_struct.get_timestamp();
@@ -92,6 +126,21 @@ mod tests {
         assert_eq!(cam.camera_y, 180);
     }
 
+    #[test]
+    fn generated_header_camera_type() {
+        for cam_type in [CameraType::DVS128, CameraType::DAVIS240] {
+            let expected = CameraParameters::new(cam_type.clone());
+            let header = aedat_header(&cam_type, "events.csv").into_bytes();
+
+            let cam = parse_camera_type(&header).unwrap();
+            assert_eq!(
+                (cam.camera_x, cam.camera_y),
+                (expected.camera_x, expected.camera_y)
+            );
+            assert_eq!(find_header_end(&header).unwrap() as usize, header.len());
+        }
+    }
+
     #[test]
     fn downres_bounds() {
         let downres_128 = Downres::new(128, 128, 4, 4);
@@ -139,7 +188,7 @@ mod tests {
     #[test]
     fn downres_to_pgm() {
         let downres_128_scale4 = Downres::new(128, 128, 4, 1);
-        let downres_128_scale4_pgm = downres_128_scale4.to_pgm();
+        let downres_128_scale4_pgm = downres_128_scale4.to_ascii_pgm(false);
         let downres_128_scale4_resolution =
             downres_128_scale4_pgm.split("\n").collect::<Vec<_>>()[1];
         assert_eq!(downres_128_scale4_resolution, "32 32");
@@ -147,7 +196,7 @@ mod tests {
         let mut downres_128_scale16 = Downres::new(128, 128, 16, 1);
 
         assert_eq!(
-            downres_128_scale16.to_pgm(),
+            downres_128_scale16.to_ascii_pgm(false),
             "P2\n8 8\n1\n\
         0 0 0 0 0 0 0 0\n\
         0 0 0 0 0 0 0 0\n\
@@ -170,7 +219,7 @@ mod tests {
         };
 
         assert_eq!(
-            downres_128_scale16.to_pgm(),
+            downres_128_scale16.to_ascii_pgm(false),
             "P2\n8 8\n1\n\
         1 0 0 0 0 0 0 0\n\
         0 0 0 0 0 0 0 0\n\
@@ -185,7 +234,7 @@ mod tests {
         downres_128_scale16.reset();
 
         assert_eq!(
-            downres_128_scale16.to_pgm(),
+            downres_128_scale16.to_ascii_pgm(false),
             "P2\n8 8\n1\n\
         0 0 0 0 0 0 0 0\n\
         0 0 0 0 0 0 0 0\n\
@@ -197,4 +246,1214 @@ mod tests {
         0 0 0 0 0 0 0 0\n"
         );
     }
+
+    #[test]
+    fn downres_sliding_counts() {
+        let mut downres = Downres::new(128, 128, 16, 1);
+        for (x, y) in [(1, 1), (2, 2), (128, 128)] {
+            downres.increment_pixel(x, y).unwrap();
+        }
+
+        // Events leaving a sliding window are removed from the counts
+        downres.decrement_pixel(1, 1).unwrap();
+        downres.decrement_pixel(128, 128).unwrap();
+        assert_eq!(downres.get_pixel(2, 2), Some(1));
+        assert_eq!(downres.get_pixel(128, 128), Some(0));
+
+        downres.decrement_pixel(2, 2).unwrap();
+        assert_eq!(
+            downres.to_ascii_pgm(false),
+            Downres::new(128, 128, 16, 1).to_ascii_pgm(false)
+        );
+        assert!(downres.decrement_pixel(0, 0).is_err());
+    }
+
+    #[test]
+    fn downres_grey_levels() {
+        let mut downres = Downres::new(4, 2, 2, 2);
+        for (x, y) in [(1, 1), (2, 2), (2, 1), (3, 1), (4, 1)] {
+            downres.increment_pixel(x, y).unwrap();
+        }
+
+        assert_eq!(downres.grey_levels(false), (vec![1, 1], 1));
+        assert_eq!(downres.grey_levels(true), (vec![3, 2], 3));
+        assert_eq!(downres.to_ascii_pgm(true), "P2\n2 1\n3\n3 2\n");
+        assert_eq!(downres.to_binary_pgm(true), b"P5\n2 1\n3\n\x03\x02");
+
+        // Pixels below the threshold are black in both modes
+        downres.decrement_pixel(3, 1).unwrap();
+        assert_eq!(downres.grey_levels(true), (vec![3, 0], 3));
+    }
+
+    #[test]
+    fn transform_coords() {
+        let cam = CameraParameters::new(CameraType::DAVIS240);
+
+        let identity = Transform::default();
+        assert_eq!(identity.dimensions(&cam), (240, 180));
+        assert_eq!(identity.apply((1, 1), &cam), (1, 1));
+
+        let flipped = Transform {
+            flip_x: true,
+            flip_y: true,
+            ..Transform::default()
+        };
+        assert_eq!(flipped.apply((1, 1), &cam), (240, 180));
+        assert_eq!(flipped.apply((240, 180), &cam), (1, 1));
+
+        let mut rotated = Transform {
+            rotation: Rotation::Rotate90,
+            ..Transform::default()
+        };
+        assert_eq!(rotated.dimensions(&cam), (180, 240));
+        assert_eq!(rotated.apply((1, 1), &cam), (180, 1));
+        assert_eq!(rotated.apply((240, 180), &cam), (1, 240));
+
+        rotated.rotation = Rotation::Rotate180;
+        assert_eq!(rotated.dimensions(&cam), (240, 180));
+        assert_eq!(rotated.apply((1, 1), &cam), (240, 180));
+
+        rotated.rotation = Rotation::Rotate270;
+        assert_eq!(rotated.dimensions(&cam), (180, 240));
+        assert_eq!(rotated.apply((1, 1), &cam), (1, 240));
+        assert_eq!(rotated.apply((240, 180), &cam), (180, 1));
+
+        let mut transposed = Transform {
+            transpose: true,
+            ..Transform::default()
+        };
+        assert_eq!(transposed.dimensions(&cam), (180, 240));
+        assert_eq!(transposed.apply((1, 180), &cam), (180, 1));
+        assert_eq!(transposed.apply_f32((2.5, 3.0), &cam), (3.0, 2.5));
+
+        // Transposing and rotating by 90 degrees mirrors the original x axis
+        transposed.rotation = Rotation::Rotate90;
+        assert_eq!(transposed.dimensions(&cam), (240, 180));
+        assert_eq!(transposed.apply((1, 1), &cam), (240, 1));
+        assert_eq!(transposed.apply((240, 180), &cam), (1, 180));
+    }
+
+    #[test]
+    fn undistortion_lookup() {
+        // 2x2 sensor where the last pixel is pushed outside of the sensor
+        let undistortion =
+            Undistortion::from_lookup(2, 2, vec![(1.2, 0.9), (1.6, 1.0), (1.0, 2.4), (2.6, 2.0)]);
+
+        assert_eq!(undistortion.undistort((2, 1)), (1.6, 1.0));
+        assert_eq!(undistortion.undistort_rounded((1, 1)), Some((1, 1)));
+        assert_eq!(undistortion.undistort_rounded((2, 1)), Some((2, 1)));
+        assert_eq!(undistortion.undistort_rounded((1, 2)), Some((1, 2)));
+        assert_eq!(undistortion.undistort_rounded((2, 2)), None);
+    }
+
+    #[test]
+    fn decay_canvas() {
+        let mut canvas = DecayCanvas::new(128, 128, 1000);
+
+        canvas.add_event((5, 10), true, 0);
+        assert_eq!(canvas.intensity((5, 10), 0), (1.0, 0.0));
+        assert_eq!(canvas.intensity((10, 5), 0), (0.0, 0.0));
+
+        let (on, off) = canvas.intensity((5, 10), 1000);
+        assert!((on - (-1.0f32).exp()).abs() < 1e-6);
+        assert_eq!(off, 0.0);
+
+        // New events are added on top of the decayed intensity
+        canvas.add_event((5, 10), true, 1000);
+        canvas.add_event((5, 10), false, 1000);
+        let (on, off) = canvas.intensity((5, 10), 1000);
+        assert!((on - (1.0 + (-1.0f32).exp())).abs() < 1e-6);
+        assert_eq!(off, 1.0);
+    }
+
+    #[test]
+    fn time_surface_sample() {
+        let mut surface = TimeSurface::new(2, 2);
+        surface.add_event((1, 1), true, 0);
+        surface.add_event((2, 2), false, 500);
+        surface.add_event((1, 1), true, 1000);
+
+        assert_eq!(surface.value((1, 1), 1000, 500), (1.0, 0.0));
+        assert_eq!(surface.value((2, 1), 1000, 500), (0.0, 0.0));
+
+        let sample = surface.sample(1000, 500);
+        assert_eq!(sample.len(), 8);
+        assert_eq!(sample[0], 1.0);
+        assert!((sample[7] - (-1.0f32).exp()).abs() < 1e-6);
+        assert_eq!(sample.iter().filter(|&&v| v > 0.0).count(), 2);
+    }
+
+    #[test]
+    fn npy_header() {
+        let mut npy = Vec::new();
+        write_npy(&mut npy, &[2, 3], &[0.0f32; 6]).unwrap();
+
+        let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(npy.len(), 10 + header_len + 6 * 4);
+
+        let header = String::from_utf8_lossy(&npy[10..10 + header_len]);
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+
+        assert!(write_npy(&mut Vec::new(), &[4], &[0i64; 3]).is_err());
+    }
+
+    fn dvs128_event(x: u8, y: u8, polarity: bool, timestamp: i32) -> Event {
+        let t = timestamp.to_be_bytes();
+        Event {
+            bytes: [
+                0,
+                0,
+                128 - y,
+                ((128 - x) << 1) | u8::from(polarity),
+                t[0],
+                t[1],
+                t[2],
+                t[3],
+            ],
+        }
+    }
+
+    #[test]
+    fn tensor_windows() {
+        let events = vec![
+            dvs128_event(1, 1, true, 100),
+            dvs128_event(2, 1, false, 150),
+            dvs128_event(1, 1, true, 350),
+            dvs128_event(1, 2, true, 400),
+        ];
+
+        let windows = split_windows(&events, &WindowMode::Time, 100);
+        let ranges: Vec<_> = windows.iter().map(|w| w.events.clone()).collect();
+        assert_eq!(ranges, vec![0..2, 2..2, 2..3, 3..4]);
+        assert_eq!(windows[1].start_time, 200);
+        assert_eq!(windows[3].end_time, 500);
+
+        let windows = split_windows(&events, &WindowMode::EventCount, 3);
+        let ranges: Vec<_> = windows.iter().map(|w| w.events.clone()).collect();
+        assert_eq!(ranges, vec![0..3, 3..4]);
+        assert_eq!((windows[0].start_time, windows[0].end_time), (100, 350));
+    }
+
+    #[test]
+    fn overlapping_windows() {
+        let events = vec![
+            dvs128_event(1, 1, true, 100),
+            dvs128_event(2, 1, false, 150),
+            dvs128_event(1, 1, true, 350),
+            dvs128_event(1, 2, true, 400),
+        ];
+
+        let windows = sliding_windows(&events, 100, 50, usize::MAX);
+        let ranges: Vec<_> = windows.iter().map(|w| w.events.clone()).collect();
+        assert_eq!(ranges, vec![0..2, 1..2, 2..2, 2..2, 2..3, 2..4, 3..4]);
+        assert_eq!((windows[6].start_time, windows[6].end_time), (400, 500));
+
+        // A stride larger than the window skips events between windows
+        let windows = sliding_windows(&events, 10, 250, usize::MAX);
+        let ranges: Vec<_> = windows.iter().map(|w| w.events.clone()).collect();
+        assert_eq!(ranges, vec![0..1, 2..3]);
+
+        // Windows stop at the limit rather than covering the whole recording
+        let windows = sliding_windows(&events, 100, 1, 3);
+        let ranges: Vec<_> = windows.iter().map(|w| w.events.clone()).collect();
+        assert_eq!(ranges, vec![0..2, 1..2, 1..2]);
+    }
+
+    #[test]
+    fn video_frames() {
+        let events: Vec<Event> = [0, 50, 120, 130, 260, 300]
+            .into_iter()
+            .map(|t| dvs128_event(1, 1, true, t))
+            .collect();
+        let ranges =
+            |frames: &[Window]| frames.iter().map(|f| f.events.clone()).collect::<Vec<_>>();
+
+        // The event that ends a time window is the last event of its frame, and the next
+        // window starts at that event
+        let frames = time_based_frames(&events, 100, None, usize::MAX);
+        assert_eq!(ranges(&frames), vec![0..3, 3..5, 5..6]);
+        let times: Vec<_> = frames.iter().map(|f| (f.start_time, f.end_time)).collect();
+        assert_eq!(times, vec![(0, 100), (120, 220), (260, 360)]);
+
+        // Frames follow each other without gaps or overlaps
+        for pair in frames.windows(2) {
+            assert_eq!(pair[0].events.end, pair[1].events.start);
+            assert!(pair[0].start_time < pair[1].start_time);
+        }
+
+        let frames = time_based_frames(&events, 100, None, 2);
+        assert_eq!(ranges(&frames), vec![0..3, 3..5]);
+
+        let frames = time_based_frames(&events, 100, Some(50), 3);
+        assert_eq!(ranges(&frames), vec![0..2, 1..4, 2..4]);
+
+        assert!(time_based_frames(&[], 100, None, usize::MAX).is_empty());
+
+        // Remaining events form the last event based frame
+        let frames = event_based_frames(&events, 4, usize::MAX);
+        assert_eq!(ranges(&frames), vec![0..4, 4..6]);
+        assert_eq!((frames[0].start_time, frames[0].end_time), (0, 130));
+        assert_eq!((frames[1].start_time, frames[1].end_time), (260, 300));
+
+        let frames = event_based_frames(&events, 2, 2);
+        assert_eq!(ranges(&frames), vec![0..2, 2..4]);
+    }
+
+    #[test]
+    fn decay_frames_fade_without_events() {
+        let matches = crate::cli()
+            .try_get_matches_from([
+                "aedat_reader",
+                "vid",
+                "rec.aedat",
+                "--time_based",
+                "-w",
+                "100",
+                "--stride",
+                "100",
+                "--render",
+                "decay",
+                "--decay_time",
+                "100",
+            ])
+            .unwrap();
+        let vid = matches.subcommand_matches("vid").unwrap();
+        let config = VidConfig::new(vid, &FilePaths::new(vid)).unwrap();
+        let cam = CameraParameters::new(CameraType::DVS128);
+
+        let events = vec![
+            dvs128_event(1, 1, true, 0),
+            dvs128_event(1, 1, true, 50),
+            dvs128_event(2, 2, true, 350),
+        ];
+        let frames = time_based_frames(&events, 100, Some(100), usize::MAX);
+        let ranges: Vec<_> = frames.iter().map(|f| f.events.clone()).collect();
+        assert_eq!(ranges, vec![0..2, 2..2, 2..2, 2..3]);
+
+        let mut renderer = FrameRenderer::new(&config, &cam, None);
+        let mut images = Vec::new();
+        for frame in &frames {
+            for event in &events[frame.events.clone()] {
+                renderer.add_event(event);
+            }
+            images.push(renderer.render(frame.end_time));
+        }
+
+        // Frames are evaluated at the end of their window, so the trail of the first events
+        // keeps fading through the empty windows
+        let green = |frame: usize| images[frame].get_pixel(0, 0)[1];
+        assert!(green(0) > green(1) && green(1) > green(2) && green(2) > green(3));
+        assert!(images[3].get_pixel(1, 1)[1] > 0);
+    }
+
+    #[test]
+    fn event_count_window_csv() {
+        let cam = CameraParameters::new(CameraType::DVS128);
+        let config = TimeWindowConfig {
+            filename: std::env::temp_dir()
+                .join(format!("aedat_event_windows_{}.csv", std::process::id())),
+            compression: OutputCompression::None,
+            columns: vec![
+                WindowColumn::Start,
+                WindowColumn::End,
+                WindowColumn::Duration,
+                WindowColumn::On,
+                WindowColumn::Off,
+            ],
+            include_pgm: false,
+            window_mode: WindowMode::EventCount,
+            window_size: 3,
+            stride: None,
+            max_windows: u32::MAX,
+            pgm_scale: 1,
+            pgm_threshold: 0,
+            pgm_output: PgmOutput::String,
+            pgm_counts: false,
+            transform: Transform::default(),
+        };
+        let events = vec![
+            dvs128_event(1, 1, true, 100),
+            dvs128_event(2, 1, false, 150),
+            dvs128_event(3, 1, true, 200),
+            dvs128_event(1, 1, true, 300),
+            dvs128_event(1, 2, true, 320),
+            dvs128_event(1, 3, false, 450),
+            dvs128_event(1, 1, false, 500),
+        ];
+
+        create_time_window_csv(events, &config, &cam).unwrap();
+        let csv = std::fs::read_to_string(&config.filename).unwrap();
+        std::fs::remove_file(&config.filename).unwrap();
+
+        // The trailing window of a single event is incomplete and left out
+        assert_eq!(
+            csv,
+            "Start,End,Duration,On,Off\n100,200,100,2,1\n300,450,150,2,1\n"
+        );
+    }
+
+    #[test]
+    fn window_statistics() {
+        let cam = CameraParameters::new(CameraType::DVS128);
+        let events = vec![
+            dvs128_event(1, 1, true, 100),
+            dvs128_event(3, 1, false, 200),
+            dvs128_event(3, 1, true, 400),
+        ];
+        let windows = split_windows(&events, &WindowMode::EventCount, 3);
+
+        let mut stats = WindowStats::new(128, 128, None);
+        for event in &events {
+            stats
+                .update(event, true, &Transform::default(), &cam)
+                .unwrap();
+        }
+
+        let value = |column| stats.value(column, &windows[0], &events);
+        assert_eq!(value(WindowColumn::Duration), "300");
+        assert_eq!(value(WindowColumn::ActivePixels), "2");
+        assert_eq!(value(WindowColumn::EventRate), "10000.000");
+        assert_eq!(value(WindowColumn::PolarityRatio), "0.667");
+        assert_eq!(value(WindowColumn::CentroidX), "2.333");
+        assert_eq!(value(WindowColumn::CentroidY), "1.000");
+        assert_eq!(value(WindowColumn::Spread), "0.943");
+        assert_eq!(value(WindowColumn::MeanInterval), "150.000");
+
+        // Removing events that leave the window updates the running totals
+        stats
+            .update(&events[0], false, &Transform::default(), &cam)
+            .unwrap();
+        stats
+            .update(&events[1], false, &Transform::default(), &cam)
+            .unwrap();
+        stats
+            .update(&events[2], false, &Transform::default(), &cam)
+            .unwrap();
+        assert_eq!(
+            stats.value(WindowColumn::ActivePixels, &windows[0], &events),
+            "0"
+        );
+        assert_eq!(
+            stats.value(WindowColumn::CentroidX, &windows[0], &events),
+            ""
+        );
+    }
+
+    #[test]
+    fn millisecond_event_index() {
+        let events = vec![
+            dvs128_event(1, 1, true, 5_000),
+            dvs128_event(1, 1, true, 5_400),
+            dvs128_event(1, 1, true, 7_000),
+            dvs128_event(1, 1, true, 7_999),
+        ];
+
+        assert_eq!(millisecond_index(&events), vec![0, 2, 2]);
+        assert_eq!(millisecond_index(&[]), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn tensor_voxel_grid() {
+        let cam = CameraParameters::new(CameraType::DVS128);
+        let events = vec![
+            dvs128_event(1, 1, true, 0),
+            dvs128_event(2, 1, false, 50),
+            dvs128_event(1, 1, true, 100),
+        ];
+        let windows = split_windows(&events, &WindowMode::EventCount, 3);
+        let plane = 128 * 128;
+
+        let grid = voxel_grid(&events, &windows[0], 3, &Transform::default(), &cam);
+        assert_eq!(grid.len(), 3 * plane);
+        assert_eq!(grid[0], 1.0);
+        assert_eq!(grid[plane + 1], -1.0);
+        assert_eq!(grid[2 * plane], 1.0);
+        assert_eq!(grid.iter().sum::<f32>(), 1.0);
+
+        // Events between bin centers are split between both bins
+        let grid = voxel_grid(&events, &windows[0], 2, &Transform::default(), &cam);
+        assert_eq!(grid[1], -0.5);
+        assert_eq!(grid[plane + 1], -0.5);
+
+        let counts = histogram(&events, &windows[0], &Transform::default(), &cam);
+        assert_eq!(counts[0], 2.0);
+        assert_eq!(counts[plane + 1], 1.0);
+        assert_eq!(counts.iter().sum::<f32>(), 3.0);
+    }
+
+    #[test]
+    fn time_based_tensors() {
+        let cam = CameraParameters::new(CameraType::DVS128);
+        let events = || {
+            vec![
+                dvs128_event(1, 1, true, 0),
+                dvs128_event(2, 1, false, 150),
+                dvs128_event(1, 1, true, 220),
+            ]
+        };
+
+        let output = std::env::temp_dir().join(format!("aedat_tensors_{}", std::process::id()));
+        let config = |window_size: &str| {
+            let output = output.to_str().unwrap();
+            let args = [
+                "aedat_reader",
+                "tensor",
+                "rec.aedat",
+                "--time_based",
+                "-w",
+                window_size,
+                "-f",
+                "npy",
+                "--output",
+                output,
+            ];
+            let matches = crate::cli().try_get_matches_from(args).unwrap();
+            let tensor = matches.subcommand_matches("tensor").unwrap();
+            TensorConfig::new(tensor, &FilePaths::new(tensor)).unwrap()
+        };
+
+        // Only complete windows are exported, so the window holding the last event is dropped
+        create_tensors(events(), &config("100"), &cam).unwrap();
+        let index = std::fs::read_to_string(output.join("index.csv")).unwrap();
+        assert_eq!(index, "Tensor,Start,End,Events\n0,0,100,1\n1,100,200,1\n");
+        assert!(!output.join("tensor_2.npy").exists());
+
+        assert!(create_tensors(events(), &config("1000"), &cam).is_err());
+
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+
+    #[test]
+    fn palettes_and_colormaps() {
+        assert_eq!(parse_hex_color("#FF8000").unwrap().0, [255, 128, 0]);
+        assert_eq!(parse_hex_color("00ff7f").unwrap().0, [0, 255, 127]);
+        assert!(parse_hex_color("#FF80").is_err());
+        assert!(parse_hex_color("#GG0000").is_err());
+
+        assert_eq!(Palette::from_name("green_red"), Some(Palette::default()));
+        assert_eq!(
+            Palette::from_name("red_blue").unwrap().background.0,
+            [255, 255, 255]
+        );
+        assert_eq!(Palette::from_name("rainbow"), None);
+
+        assert_eq!(Colormap::Viridis.map(0.0).0, [68, 1, 84]);
+        assert_eq!(Colormap::Viridis.map(1.0).0, [253, 231, 37]);
+        assert_eq!(Colormap::Inferno.map(2.0).0, [252, 255, 164]);
+        assert_eq!(Colormap::Grey.map(0.5).0, [128, 128, 128]);
+        assert_eq!(Colormap::Grey.map(-1.0).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn count_frames() {
+        let mut unsigned = CountFrame::new(2, 2, false);
+        let mut signed = CountFrame::new(2, 2, true);
+
+        for (coords, polarity) in [
+            ((1, 1), true),
+            ((1, 1), false),
+            ((2, 2), false),
+            ((1, 1), true),
+        ] {
+            unsigned.add_event(coords, polarity);
+            signed.add_event(coords, polarity);
+        }
+
+        assert_eq!(unsigned.counts(), &[3, 0, 0, 1]);
+        assert_eq!(signed.counts(), &[1, 0, 0, -1]);
+
+        assert_eq!(
+            unsigned.normalized(CountNormalization::Max),
+            vec![1.0, 0.0, 0.0, 1.0 / 3.0]
+        );
+        assert_eq!(
+            unsigned.normalized(CountNormalization::Clip(2)),
+            vec![1.0, 0.0, 0.0, 0.5]
+        );
+        assert_eq!(
+            signed.normalized(CountNormalization::Max),
+            vec![1.0, 0.0, 0.0, -1.0]
+        );
+
+        unsigned.reset();
+        assert_eq!(unsigned.counts(), &[0, 0, 0, 0]);
+        assert_eq!(
+            unsigned.normalized(CountNormalization::Max),
+            vec![0.0, 0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn frame_event_rate() {
+        let info = FrameInfo {
+            start_time: 1_000,
+            end_time: 3_000,
+            event_count: 50,
+        };
+        assert_eq!(info.event_rate(), Some(25_000.0));
+
+        let single = FrameInfo {
+            start_time: 1_000,
+            end_time: 1_000,
+            event_count: 1,
+        };
+        assert_eq!(single.event_rate(), None);
+    }
+
+    #[test]
+    fn columnar_schema() {
+        let mut config = CsvConfig {
+            filename: "events.parquet".into(),
+            compression: OutputCompression::None,
+            format: EventFormat::Parquet,
+            include_polarity: true,
+            coords: CoordMode::XY,
+            offset_time: false,
+            transform: Transform::default(),
+            calibration: None,
+            round_undistorted: false,
+            pixel_numbering: PixelNumbering::default(),
+            formatting: CsvFormatting::default(),
+        };
+
+        let names = |config: &CsvConfig| {
+            event_schema(config)
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&config), vec!["polarity", "x", "y", "timestamp"]);
+
+        config.include_polarity = false;
+        config.coords = CoordMode::PixelNum;
+        config.calibration = Some("calibration.yaml".into());
+        assert_eq!(
+            names(&config),
+            vec!["pixel_number", "x_undist", "y_undist", "timestamp"]
+        );
+    }
+
+    #[test]
+    fn columnar_round_trip() {
+        let cam = CameraParameters::new(CameraType::DVS128);
+
+        for (format, extension) in [
+            (EventFormat::Parquet, "parquet"),
+            (EventFormat::ArrowIpc, "arrow"),
+        ] {
+            let filename = std::env::temp_dir()
+                .join(format!("aedat_columnar_{}.{extension}", std::process::id()));
+            let config = CsvConfig {
+                filename: filename.clone(),
+                compression: OutputCompression::None,
+                format,
+                include_polarity: true,
+                coords: CoordMode::XY,
+                offset_time: true,
+                transform: Transform::default(),
+                calibration: None,
+                round_undistorted: false,
+                pixel_numbering: PixelNumbering::default(),
+                formatting: CsvFormatting::default(),
+            };
+            let events = vec![
+                dvs128_event(1, 2, true, 100),
+                dvs128_event(3, 4, false, 350),
+            ];
+            create_columnar(events, &config, &cam).unwrap();
+
+            let file = std::fs::File::open(&filename).unwrap();
+            let batches = match config.format {
+                EventFormat::Parquet => ParquetRecordBatchReaderBuilder::try_new(file)
+                    .unwrap()
+                    .build()
+                    .unwrap()
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap(),
+                _ => FileReader::try_new(file, None)
+                    .unwrap()
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap(),
+            };
+            std::fs::remove_file(&filename).unwrap();
+
+            assert_eq!(batches.len(), 1);
+            let batch = &batches[0];
+            let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+
+            let polarity = column("polarity");
+            let polarity = polarity.as_any().downcast_ref::<Int8Array>().unwrap();
+            assert_eq!(polarity.values(), &[1, -1]);
+            let x = column("x");
+            let x = x.as_any().downcast_ref::<UInt16Array>().unwrap();
+            assert_eq!(x.values(), &[1, 3]);
+            let y = column("y");
+            let y = y.as_any().downcast_ref::<UInt16Array>().unwrap();
+            assert_eq!(y.values(), &[2, 4]);
+            let timestamp = column("timestamp");
+            let timestamp = timestamp.as_any().downcast_ref::<Int64Array>().unwrap();
+            assert_eq!(timestamp.values(), &[0, 250]);
+        }
+    }
+
+    #[test]
+    fn structured_npy_events() {
+        let cam = CameraParameters::new(CameraType::DVS128);
+        let config = CsvConfig {
+            filename: "events.npy".into(),
+            compression: OutputCompression::None,
+            format: EventFormat::Npy,
+            include_polarity: true,
+            coords: CoordMode::XY,
+            offset_time: true,
+            transform: Transform::default(),
+            calibration: None,
+            round_undistorted: false,
+            pixel_numbering: PixelNumbering::default(),
+            formatting: CsvFormatting::default(),
+        };
+        let events = vec![
+            dvs128_event(1, 2, true, 100),
+            dvs128_event(3, 4, false, 350),
+        ];
+
+        let mut buf = Vec::new();
+        write_events_npy(&mut buf, &events, &config, &cam).unwrap();
+
+        let header_len = usize::from(u16::from_le_bytes([buf[8], buf[9]]));
+        let header = std::str::from_utf8(&buf[10..10 + header_len]).unwrap();
+        assert!(header.starts_with(
+            "{'descr': [('t', '<i8'), ('x', '<u2'), ('y', '<u2'), ('p', '<i1')], \
+             'fortran_order': False, 'shape': (2,), }"
+        ));
+
+        // Packed 13 byte records with timestamps relative to the first event
+        let records = &buf[10 + header_len..];
+        assert_eq!(records.len(), 2 * 13);
+        assert_eq!(&records[..13], &[0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2, 0, 1]);
+        assert_eq!(&records[13..21], &250i64.to_le_bytes());
+        assert_eq!(&records[21..], &[3, 0, 4, 0, 0xff]);
+    }
+
+    #[test]
+    fn event_encoding() {
+        for cam_type in [CameraType::DVS128, CameraType::DAVIS240] {
+            let cam = CameraParameters::new(cam_type);
+            for (x, y, polarity) in [
+                (1, 1, true),
+                (cam.camera_x, cam.camera_y, false),
+                (37, 5, true),
+            ] {
+                let event = Event::new(x, y, polarity, -1672025907, &cam.camera_type);
+                assert_eq!(event.get_coords(&cam.camera_type), (x, y));
+                assert_eq!(event.get_polarity(&cam.camera_type), polarity);
+                assert_eq!(event.get_timestamp(), -1672025907);
+            }
+        }
+
+        // Encoding matches the bytes of a recorded event
+        let event = Event::new(13, 72, true, -1672025907, &CameraType::DVS128);
+        assert_eq!(event.bytes, [0, 0, 56, 231, 156, 86, 232, 205]);
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        let cam = CameraParameters::new(CameraType::DAVIS240);
+        let events: Vec<Event> = (0..10)
+            .map(|i| {
+                Event::new(
+                    i * 20 + 1,
+                    180 - i,
+                    i % 3 == 0,
+                    1_000 * i32::from(i) - 5_000,
+                    &cam.camera_type,
+                )
+            })
+            .collect();
+
+        for compression in [0, 3] {
+            let options = CompactOptions {
+                block_size: 4,
+                compression,
+            };
+            let mut buf = Vec::new();
+            write_compact(&mut buf, &events, "#!AER-DAT2.0\r\n", &cam, &options).unwrap();
+
+            let mut reader = CompactReader::new(std::io::Cursor::new(buf)).unwrap();
+            assert_eq!(reader.event_count(), 10);
+            assert_eq!(reader.header(), "#!AER-DAT2.0\r\n");
+            assert_eq!(reader.blocks().len(), 3);
+            assert_eq!(
+                (reader.blocks()[1].min_time, reader.blocks()[1].max_time),
+                (-1_000, 2_000)
+            );
+
+            let decoded = reader.read_events().unwrap();
+            let bytes = |events: &[Event]| events.iter().map(|e| e.bytes).collect::<Vec<_>>();
+            assert_eq!(bytes(&decoded), bytes(&events));
+
+            // Only the events inside the time range are returned
+            let range = reader.read_time_range(0, 3_000).unwrap();
+            assert_eq!(bytes(&range), bytes(&events[5..8]));
+        }
+
+        // Header and index sizes that exceed the file are rejected before reading them
+        let options = CompactOptions {
+            block_size: 4,
+            compression: 0,
+        };
+        let mut buf = Vec::new();
+        write_compact(&mut buf, &events, "", &cam, &options).unwrap();
+        for field in [16..20, 20..24] {
+            let mut corrupt = buf.clone();
+            corrupt[field].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert!(CompactReader::new(std::io::Cursor::new(corrupt)).is_err());
+        }
+        assert!(CompactReader::new(std::io::Cursor::new(&buf[..buf.len() - 1])).is_err());
+    }
+
+    #[test]
+    fn csv_event_lines() {
+        let cam = CameraParameters::new(CameraType::DVS128);
+        let mut config = CsvConfig {
+            filename: std::env::temp_dir().join(format!("aedat_csv_{}.csv", std::process::id())),
+            compression: OutputCompression::None,
+            format: EventFormat::Csv,
+            include_polarity: true,
+            coords: CoordMode::XY,
+            offset_time: false,
+            transform: Transform::default(),
+            calibration: None,
+            round_undistorted: false,
+            pixel_numbering: PixelNumbering::default(),
+            formatting: CsvFormatting::default(),
+        };
+        let event = dvs128_event(1, 2, false, 1_500);
+
+        let line = |config: &CsvConfig, time_offset: i32| {
+            let mut buf = Vec::new();
+            let columns = config.csv_columns();
+            write_event_line(&mut buf, &event, &columns, config, &cam, None, time_offset).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(line(&config, 0), "-1,1,2,1500\n");
+
+        config.formatting.delimiter = '\t';
+        config.formatting.polarity = PolarityEncoding::Binary;
+        config.formatting.time_unit = TimeUnit::Milliseconds;
+        assert_eq!(line(&config, 0), "0\t1\t2\t1.500\n");
+
+        config.formatting.time_unit = TimeUnit::Seconds;
+        assert_eq!(line(&config, 500), "0\t1\t2\t0.001000\n");
+
+        // ON events are 1 with either polarity encoding
+        config.formatting.delimiter = ',';
+        config.formatting.time_unit = TimeUnit::Microseconds;
+        let events = vec![
+            dvs128_event(1, 2, true, 100),
+            dvs128_event(3, 4, false, 350),
+        ];
+        create_csv(events, &config, &cam).unwrap();
+        let csv = std::fs::read_to_string(&config.filename).unwrap();
+        assert_eq!(csv, "On/Off,X,Y,Timestamp\n1,1,2,100\n0,3,4,350\n");
+
+        // A time range without events leaves only the header, even with --offset_time
+        config.offset_time = true;
+        create_csv(Vec::new(), &config, &cam).unwrap();
+        let csv = std::fs::read_to_string(&config.filename).unwrap();
+        assert_eq!(csv, "On/Off,X,Y,Timestamp\n");
+        config.offset_time = false;
+
+        // --no_header leaves out the header line
+        config.formatting.header = false;
+        let events = vec![dvs128_event(1, 2, true, 100)];
+        create_csv(events, &config, &cam).unwrap();
+        let csv = std::fs::read_to_string(&config.filename).unwrap();
+        std::fs::remove_file(&config.filename).unwrap();
+        assert_eq!(csv, "1,1,2,100\n");
+    }
+
+    #[test]
+    fn csv_column_order() {
+        let mut config = CsvConfig {
+            filename: "events.csv".into(),
+            compression: OutputCompression::None,
+            format: EventFormat::Csv,
+            include_polarity: true,
+            coords: CoordMode::XY,
+            offset_time: false,
+            transform: Transform::default(),
+            calibration: None,
+            round_undistorted: false,
+            pixel_numbering: PixelNumbering::default(),
+            formatting: CsvFormatting::default(),
+        };
+        assert_eq!(
+            config.csv_columns(),
+            vec![
+                EventColumn::Polarity,
+                EventColumn::X,
+                EventColumn::Y,
+                EventColumn::Timestamp
+            ]
+        );
+
+        // Listed columns come first and the rest keep their default order
+        config.formatting.column_order = vec![EventColumn::Timestamp, EventColumn::Y];
+        assert_eq!(
+            config.csv_columns(),
+            vec![
+                EventColumn::Timestamp,
+                EventColumn::Y,
+                EventColumn::Polarity,
+                EventColumn::X
+            ]
+        );
+
+        let row_major = PixelNumbering::default();
+        assert_eq!(row_major.pixel_number((1, 1), (240, 180)), 0);
+        assert_eq!(row_major.pixel_number((3, 2), (240, 180)), 242);
+
+        let column_major = PixelNumbering {
+            order: PixelOrder::ColumnMajor,
+            base: 1,
+        };
+        assert_eq!(column_major.pixel_number((1, 1), (240, 180)), 1);
+        assert_eq!(column_major.pixel_number((3, 2), (240, 180)), 362);
+    }
+
+    #[test]
+    fn compressed_streams() {
+        use std::{io::Write, path::Path};
+
+        assert_eq!(
+            OutputCompression::from_path(Path::new("events.csv.gz")),
+            OutputCompression::Gzip
+        );
+        assert_eq!(
+            OutputCompression::Zstd.apply_extension("events.csv".into()),
+            Path::new("events.csv.zst")
+        );
+        assert_eq!(
+            OutputCompression::Gzip.apply_extension("events.csv.gz".into()),
+            Path::new("events.csv.gz")
+        );
+        assert_eq!(
+            strip_compression_extension(Path::new("dvs.aedat.zst")).with_extension("csv"),
+            Path::new("dvs.csv")
+        );
+
+        let data = b"#!AER-DAT2.0\r\n".repeat(10);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(&data).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(decompress_input(gzip).unwrap(), data);
+
+        let zstd = zstd::encode_all(data.as_slice(), 0).unwrap();
+        assert_eq!(decompress_input(zstd).unwrap(), data);
+
+        // Uncompressed data is passed through
+        assert_eq!(decompress_input(data.clone()).unwrap(), data);
+    }
+
+    #[test]
+    fn csv_event_import() {
+        let config = CsvInputConfig {
+            columns: vec![
+                Some(EventColumn::Timestamp),
+                None,
+                Some(EventColumn::X),
+                Some(EventColumn::Y),
+                Some(EventColumn::Polarity),
+            ],
+            delimiter: '\t',
+            time_unit: TimeUnit::Seconds,
+            polarity: PolarityEncoding::Binary,
+            coord_base: 0,
+            camera_type: CameraType::DAVIS240,
+        };
+
+        let csv = "t\tid\tx\ty\tp\n0.002\t7\t239\t0\t0\n0.001\t8\t0\t179\t1\n";
+        let events = parse_csv_events(csv.as_bytes(), &config).unwrap();
+
+        // The header is skipped, events are sorted and coordinates become 1-based
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].get_timestamp(), 1_000);
+        assert_eq!(events[0].get_coords(&CameraType::DAVIS240), (1, 180));
+        assert!(events[0].get_polarity(&CameraType::DAVIS240));
+        assert_eq!(events[1].get_timestamp(), 2_000);
+        assert_eq!(events[1].get_coords(&CameraType::DAVIS240), (240, 1));
+        assert!(!events[1].get_polarity(&CameraType::DAVIS240));
+
+        // Coordinates outside of the sensor and malformed rows are rejected
+        assert!(parse_csv_events(b"0.001\t0\t240\t0\t1\n", &config).is_err());
+        assert!(parse_csv_events(b"0.001\t0\t1\t1\t1\n0.002\t0\t1\t1\t-1\n", &config).is_err());
+    }
+
+    #[test]
+    fn batch_inputs() {
+        let root = std::env::temp_dir().join(format!("aedat_batch_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("day1")).unwrap();
+        std::fs::create_dir_all(root.join("day2")).unwrap();
+        for file in [
+            "day1/a.aedat",
+            "day1/notes.txt",
+            "day2/b.aedat.gz",
+            "day2/c.csv",
+        ] {
+            std::fs::write(root.join(file), b"").unwrap();
+        }
+
+        // Directories are searched recursively for event files, and keep their own name
+        let inputs = collect_inputs(std::slice::from_ref(&root), false).unwrap();
+        let relative: Vec<_> = inputs.iter().map(|i| i.relative.clone()).collect();
+        let root_name = PathBuf::from(root.file_name().unwrap());
+        assert_eq!(
+            relative,
+            [
+                root_name.join("day1/a.aedat"),
+                root_name.join("day2/b.aedat.gz")
+            ]
+        );
+        assert_eq!(
+            collect_inputs(std::slice::from_ref(&root), true)
+                .unwrap()
+                .len(),
+            3
+        );
+
+        // Outputs mirror the location of each input under the output directory
+        let paths = inputs[1].file_paths(Some(std::path::Path::new("out")));
+        assert_eq!(paths.input, root.join("day2/b.aedat.gz"));
+        assert_eq!(
+            paths.output_dir.unwrap(),
+            PathBuf::from("out").join(&root_name).join("day2")
+        );
+
+        // Globs are mirrored relative to the directory before the first wildcard
+        let inputs = collect_inputs(&[root.join("day*/*.csv")], false).unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].relative, std::path::Path::new("day2/c.csv"));
+        assert!(collect_inputs(&[root.join("*.aedc")], false).is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn batch_outputs() {
+        let root = std::env::temp_dir().join(format!("aedat_batch_outputs_{}", std::process::id()));
+        for dir in ["run1", "run2"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("rec.aedat"), b"").unwrap();
+        }
+        let out = root.join("out");
+        let convert = |_: &FilePaths| Ok(());
+
+        // Files of the same name in two input directories are written to their own directories
+        let inputs = collect_inputs(&[root.join("run1"), root.join("run2")], false).unwrap();
+        let relative: Vec<_> = inputs.iter().map(|i| i.relative.clone()).collect();
+        assert_eq!(
+            relative,
+            [
+                PathBuf::from("run1/rec.aedat"),
+                PathBuf::from("run2/rec.aedat")
+            ]
+        );
+        let results = run_batch(&inputs, Some(&out), 1, convert).unwrap();
+        assert!(results.iter().all(|r| r.result.is_ok()));
+        assert!(out.join("run1").is_dir() && out.join("run2").is_dir());
+
+        // Explicitly named files are placed directly in the output directory, so files of the
+        // same name would overwrite each other
+        let files = [root.join("run1/rec.aedat"), root.join("run2/rec.aedat")];
+        let inputs = collect_inputs(&files, false).unwrap();
+        assert!(run_batch(&inputs, Some(&out), 1, convert).is_err());
+        assert!(run_batch(&inputs, None, 1, convert).is_ok());
+
+        // Outputs are named without the input extension
+        std::fs::write(root.join("run1/rec.aedc"), b"").unwrap();
+        let inputs = collect_inputs(&[root.join("run1")], false).unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert!(run_batch(&inputs, None, 1, convert).is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn output_paths() {
+        let paths = |input: &str, output: Option<&str>, output_dir: Option<&str>| FilePaths {
+            input: input.into(),
+            output: output.map(Into::into),
+            output_dir: output_dir.map(Into::into),
+        };
+        let csv = |f: PathBuf| f.with_extension("csv");
+
+        // Derived from the input filename without its compression extension
+        let derived = output_filename(&paths("run/rec.aedat.gz", None, None), true, csv);
+        assert_eq!(derived.unwrap(), PathBuf::from("run/rec.csv"));
+        let derived = output_filename(&paths("run/rec.aedat", None, Some("out")), true, csv);
+        assert_eq!(derived.unwrap(), PathBuf::from("out/rec.csv"));
+
+        let explicit = output_filename(&paths("rec.aedat", Some("x.avi"), None), false, csv);
+        assert_eq!(explicit.unwrap(), PathBuf::from("x.avi"));
+        let overwrite = output_filename(&paths("rec.aedat", Some("rec.aedat"), None), false, csv);
+        assert!(overwrite.is_err());
+
+        // Only text outputs go to stdout, which is the default when reading from stdin
+        let stdout = output_filename(&paths("rec.aedat", Some("-"), None), true, csv);
+        assert_eq!(stdout.unwrap(), PathBuf::from("-"));
+        assert!(output_filename(&paths("rec.aedat", Some("-"), None), false, csv).is_err());
+        let stdin = output_filename(&paths("-", None, None), true, csv);
+        assert_eq!(stdin.unwrap(), PathBuf::from("-"));
+        assert!(output_filename(&paths("-", None, None), false, csv).is_err());
+        let stdin = output_filename(&paths("-", Some("x.npy"), None), false, csv);
+        assert_eq!(stdin.unwrap(), PathBuf::from("x.npy"));
+
+        // --compress appends its extension, except on stdout; otherwise the extension of the
+        // output selects the compression
+        let text = |cli: &[&str]| {
+            let mut args = vec!["aedat_reader", "csv", "-c", "-i"];
+            args.extend(cli);
+            let matches = crate::cli().try_get_matches_from(args).unwrap();
+            let csv_matches = matches.subcommand_matches("csv").unwrap();
+            text_output(csv_matches, &FilePaths::new(csv_matches), csv)
+        };
+        assert_eq!(
+            text(&["rec.aedat", "--compress", "gzip"]).unwrap(),
+            (PathBuf::from("rec.csv.gz"), OutputCompression::Gzip)
+        );
+        assert_eq!(
+            text(&["-", "--compress", "zstd"]).unwrap(),
+            (PathBuf::from("-"), OutputCompression::Zstd)
+        );
+        assert_eq!(
+            text(&["rec.aedat", "--output", "x.csv.zst"]).unwrap(),
+            (PathBuf::from("x.csv.zst"), OutputCompression::Zstd)
+        );
+        assert!(text(&["rec.csv.gz", "--output", "rec", "--compress", "gzip"]).is_ok());
+        assert!(text(&["rec.csv.gz", "--output", "rec.csv", "--compress", "gzip"]).is_err());
+
+        // An explicit tensor output is used as given, even when it is an npy directory
+        let tensor = |cli: &[&str]| {
+            let mut args = vec![
+                "aedat_reader",
+                "tensor",
+                "rec.aedat",
+                "--event_based",
+                "-w",
+                "100",
+            ];
+            args.extend(cli);
+            let matches = crate::cli().try_get_matches_from(args).unwrap();
+            let tensor_matches = matches.subcommand_matches("tensor").unwrap();
+            TensorConfig::new(tensor_matches, &FilePaths::new(tensor_matches))
+                .unwrap()
+                .filename
+        };
+        assert_eq!(tensor(&[]), PathBuf::from("rec_tensors.npz"));
+        assert_eq!(tensor(&["-f", "npy"]), PathBuf::from("rec_tensors"));
+        assert_eq!(
+            tensor(&["-f", "npy", "--output", "runs/v1.2"]),
+            PathBuf::from("runs/v1.2")
+        );
+    }
+
+    #[test]
+    fn config_file_settings() {
+        let command = crate::cli();
+
+        let path = std::env::temp_dir().join(format!("aedat_config_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "flip_x = true\n\
+             [vid]\ntime_based = true\nwindow_size = 1000\npalette = \"green_red\"\n\
+             [csv]\ncoords = true\ninclude_polarity = true\ncolumn_order = [\"t\", \"x\"]\n",
+        )
+        .unwrap();
+
+        let args = |cli: &[&str], path: &std::path::Path| {
+            let mut args = vec!["aedat_reader"];
+            args.extend(cli);
+            args.extend(["--config", path.to_str().unwrap()]);
+            let args = apply_config_file(&command, args.into_iter().map(Into::into).collect());
+            command.clone().try_get_matches_from(args.unwrap()).unwrap()
+        };
+
+        // File settings fill in required options and groups such as reconstructionMethod
+        let matches = args(&["vid", "in.aedat"], &path);
+        let vid = matches.subcommand_matches("vid").unwrap();
+        assert!(vid.get_flag("timeBasedReconstruction") && vid.get_flag("flipX"));
+        assert_eq!(vid.get_one::<usize>("windowSize"), Some(&1000));
+
+        // Command line options replace file settings and the options they conflict with
+        let matches = args(&["vid", "in.aedat", "--event_based", "-w", "50"], &path);
+        let vid = matches.subcommand_matches("vid").unwrap();
+        assert!(vid.get_flag("eventBasedReconstruction"));
+        assert!(!vid.get_flag("timeBasedReconstruction"));
+        assert_eq!(vid.get_one::<usize>("windowSize"), Some(&50));
+
+        // Lists are replaced rather than extended. Settings are inserted after the subcommand,
+        // here given as its flag, and not after a filename that matches a subcommand name
+        let column_order = |matches: &clap::ArgMatches| {
+            let csv = matches.subcommand_matches("csv").unwrap();
+            csv.get_many::<String>("columnOrder")
+                .unwrap()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(column_order(&args(&["--csv", "vid"], &path)), ["t", "x"]);
+        assert_eq!(
+            column_order(&args(&["csv", "in.aedat", "--column_order", "y"], &path)),
+            ["y"]
+        );
+
+        // The effective options can be printed without a filename and loaded again
+        let matches = args(&["vid", "--print_config", "--event_based"], &path);
+        let vid = matches.subcommand_matches("vid").unwrap();
+        let toml = config_toml(command.find_subcommand("vid").unwrap(), vid);
+        assert!(toml.starts_with("[vid]\n"));
+        assert!(toml.contains("event_based = true\n") && toml.contains("window_size = 1000\n"));
+        assert!(!toml.contains("time_based"));
+
+        let printed = path.with_extension("printed.toml");
+        std::fs::write(&printed, toml).unwrap();
+        let matches = args(&["vid", "in.aedat"], &printed);
+        let vid = matches.subcommand_matches("vid").unwrap();
+        assert!(vid.get_flag("eventBasedReconstruction") && vid.get_flag("flipX"));
+        assert_eq!(vid.get_one::<usize>("windowSize"), Some(&1000));
+
+        // A top-level setting applies to every subcommand with an option of that name
+        let shared = path.with_extension("shared.toml");
+        std::fs::write(
+            &shared,
+            "compress = \"zstd\"\n[tensor]\ncompress_npz = true\n",
+        )
+        .unwrap();
+        for cli in [
+            &["csv", "in.aedat", "-c", "-i"][..],
+            &["time_windows", "in.aedat", "-w", "10"],
+        ] {
+            let matches = args(cli, &shared);
+            let matches = matches.subcommand_matches(cli[0]).unwrap();
+            assert_eq!(
+                matches.get_one::<String>("compress").map(String::as_str),
+                Some("zstd")
+            );
+        }
+        let matches = args(
+            &["tensor", "in.aedat", "--event_based", "-w", "10"],
+            &shared,
+        );
+        let tensor = matches.subcommand_matches("tensor").unwrap();
+        assert!(tensor.get_flag("compressNpz"));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&printed).unwrap();
+        std::fs::remove_file(&shared).unwrap();
+    }
 }
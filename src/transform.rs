@@ -0,0 +1,87 @@
+use crate::aedat_data::{CameraParameters, Event};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// Geometric transform applied to decoded event coordinates.
+/// Flips are applied first, then the transpose, followed by a clockwise rotation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Transform {
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Swap the x and y axes
+    pub transpose: bool,
+    pub rotation: Rotation,
+}
+
+impl Transform {
+    /// Width and height of the output after the transform has been applied
+    #[must_use]
+    pub fn dimensions(&self, cam: &CameraParameters) -> (u8, u8) {
+        let (width, height) = self.transposed_dimensions(cam);
+
+        match self.rotation {
+            Rotation::None | Rotation::Rotate180 => (width, height),
+            Rotation::Rotate90 | Rotation::Rotate270 => (height, width),
+        }
+    }
+
+    /// Width and height after the transpose, before the rotation
+    fn transposed_dimensions(&self, cam: &CameraParameters) -> (u8, u8) {
+        if self.transpose {
+            (cam.camera_y, cam.camera_x)
+        } else {
+            (cam.camera_x, cam.camera_y)
+        }
+    }
+
+    /// Map 1-based sensor coordinates to 1-based output coordinates
+    #[must_use]
+    pub fn apply(&self, (x, y): (u8, u8), cam: &CameraParameters) -> (u8, u8) {
+        let (width, height) = (cam.camera_x, cam.camera_y);
+
+        let x = if self.flip_x { width + 1 - x } else { x };
+        let y = if self.flip_y { height + 1 - y } else { y };
+
+        let (x, y) = if self.transpose { (y, x) } else { (x, y) };
+        let (width, height) = self.transposed_dimensions(cam);
+
+        match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Rotate90 => (height + 1 - y, x),
+            Rotation::Rotate180 => (width + 1 - x, height + 1 - y),
+            Rotation::Rotate270 => (y, width + 1 - x),
+        }
+    }
+
+    /// Same as `apply` for sub-pixel coordinates such as undistorted event positions
+    #[must_use]
+    pub fn apply_f32(&self, (x, y): (f32, f32), cam: &CameraParameters) -> (f32, f32) {
+        let (width, height) = (f32::from(cam.camera_x), f32::from(cam.camera_y));
+
+        let x = if self.flip_x { width + 1.0 - x } else { x };
+        let y = if self.flip_y { height + 1.0 - y } else { y };
+
+        let (x, y) = if self.transpose { (y, x) } else { (x, y) };
+        let (width, height) = self.transposed_dimensions(cam);
+        let (width, height) = (f32::from(width), f32::from(height));
+
+        match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Rotate90 => (height + 1.0 - y, x),
+            Rotation::Rotate180 => (width + 1.0 - x, height + 1.0 - y),
+            Rotation::Rotate270 => (y, width + 1.0 - x),
+        }
+    }
+
+    #[must_use]
+    pub fn event_coords(&self, event: &Event, cam: &CameraParameters) -> (u8, u8) {
+        self.apply(event.get_coords(&cam.camera_type), cam)
+    }
+}
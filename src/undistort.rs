@@ -0,0 +1,126 @@
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use opencv::calib3d::undistort_points;
+use opencv::core::{no_array, FileStorage, FileStorage_Mode, Mat, Point2f, Vector};
+use opencv::prelude::*;
+
+use crate::aedat_data::CameraParameters;
+
+// Node names used by OpenCV's calibration samples and other common tools
+const CAMERA_MATRIX_NODES: [&str; 3] = ["camera_matrix", "cameraMatrix", "K"];
+const DIST_COEFFS_NODES: [&str; 4] = ["distortion_coefficients", "dist_coeffs", "distCoeffs", "D"];
+
+fn cv_error(e: opencv::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+fn read_matrix(storage: &FileStorage, names: &[&str]) -> Result<Mat, Error> {
+    for name in names {
+        let node = storage.get(name).map_err(cv_error)?;
+
+        if !node.empty().map_err(cv_error)? {
+            return node.mat().map_err(cv_error);
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::NotFound,
+        format!("None of {names:?} were found in the calibration file"),
+    ))
+}
+
+/// Lookup table of undistorted coordinates for every pixel of the sensor.
+///
+/// The calibration is expected in OpenCV's pixel convention (0-based) for the
+/// decoded, untransformed event coordinates. Coordinates passed in and returned
+/// use the 1-based convention of `Event::get_coords`.
+pub struct Undistortion {
+    size_x: usize,
+    size_y: usize,
+    lookup: Vec<(f32, f32)>,
+}
+
+impl Undistortion {
+    /// Load OpenCV-style camera intrinsics and distortion coefficients from a YAML or JSON file
+    pub fn from_calibration_file(
+        path: &Path,
+        cam: &CameraParameters,
+    ) -> Result<Undistortion, Error> {
+        let storage = FileStorage::new(&path.to_string_lossy(), FileStorage_Mode::READ as i32, "")
+            .map_err(cv_error)?;
+
+        if !storage.is_opened().map_err(cv_error)? {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("Could not open calibration file {}", path.display()),
+            ));
+        }
+
+        let camera_matrix = read_matrix(&storage, &CAMERA_MATRIX_NODES)?;
+        let dist_coeffs = read_matrix(&storage, &DIST_COEFFS_NODES)?;
+
+        let size_x = cam.camera_x as usize;
+        let size_y = cam.camera_y as usize;
+
+        let mut pixels: Vector<Point2f> = Vector::with_capacity(size_x * size_y);
+        for y in 0..size_y {
+            for x in 0..size_x {
+                pixels.push(Point2f::new(x as f32, y as f32));
+            }
+        }
+
+        // Passing the camera matrix as the new projection keeps the result in pixel units
+        let mut undistorted: Vector<Point2f> = Vector::new();
+        undistort_points(
+            &pixels,
+            &mut undistorted,
+            &camera_matrix,
+            &dist_coeffs,
+            &no_array(),
+            &camera_matrix,
+        )
+        .map_err(cv_error)?;
+
+        let lookup = undistorted.iter().map(|p| (p.x + 1.0, p.y + 1.0)).collect();
+
+        Ok(Undistortion {
+            size_x,
+            size_y,
+            lookup,
+        })
+    }
+
+    /// Build an undistortion directly from a precomputed lookup table
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn from_lookup(size_x: usize, size_y: usize, lookup: Vec<(f32, f32)>) -> Undistortion {
+        assert_eq!(lookup.len(), size_x * size_y, "Lookup table size mismatch");
+
+        Undistortion {
+            size_x,
+            size_y,
+            lookup,
+        }
+    }
+
+    /// Undistorted floating point coordinates of a 1-based sensor pixel
+    #[must_use]
+    pub fn undistort(&self, (x, y): (u8, u8)) -> (f32, f32) {
+        self.lookup[((y as usize - 1) * self.size_x) + (x as usize - 1)]
+    }
+
+    /// Undistorted coordinates rounded to the nearest pixel.
+    /// Returns `None` if the pixel is mapped outside of the sensor
+    #[must_use]
+    pub fn undistort_rounded(&self, coords: (u8, u8)) -> Option<(u8, u8)> {
+        let (x, y) = self.undistort(coords);
+        let (x, y) = (x.round(), y.round());
+
+        if x >= 1.0 && x <= self.size_x as f32 && y >= 1.0 && y <= self.size_y as f32 {
+            Some((x as u8, y as u8))
+        } else {
+            None
+        }
+    }
+}